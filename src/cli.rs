@@ -0,0 +1,102 @@
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+
+use crate::export::ExportTarget;
+use crate::folder::SizeMode;
+use crate::ignore::IgnoreMode;
+use crate::scan::{MaxDepth, MinFraction, SymlinkPolicy, ThreadCount};
+
+const USAGE: &str = "Usage: folder-scan [OPTIONS] [PATH]
+
+Options:
+  -t, --threads <N>        Number of scanner threads (default: available cores)
+  -s, --size-mode <MODE>   Size folders by 'apparent' size, 'disk' usage or 'items' (default: apparent)
+  -x, --one-file-system    Don't descend into directories on other file systems
+  -L, --symlinks <POLICY>  Follow symlinks 'never', 'within-root' or 'always' (default: never)
+  -e, --exclude <GLOB>     Leave out entries matching the pattern (repeatable)
+  -i, --include <GLOB>     Only count files matching the pattern (repeatable)
+  -g, --gitignore <VIEW>   Split by .gitignore rules: 'off', 'tracked' or 'ignored' (default: off)
+  -m, --min-size <BYTES>   Folders up to this size don't keep their subfolders (default: 1048576)
+  -f, --min-fraction <F>   Merge folders smaller than this fraction of the total (default: 0.0001)
+  -d, --max-depth <N>      Merge folders deeper than N levels, 'none' for no limit (default: none)
+  -F, --file-size <BYTES>  Files at least this size get their own rectangle (default: 1048576)
+  -n, --largest <N>        Number of files in the largest files list (default: 100)
+  -o, --open <FILE>        Browse a saved snapshot instead of scanning
+  -I, --import <FILE>      Browse an ncdu export (ncdu -o) or the output of du -ak
+  -E, --export <FMT:FILE>  Write the tree as 'json', 'csv' or 'ncdu' to FILE and exit
+  -c, --config <FILE>      Read options from FILE instead of the default config file
+  -h, --help               Print this help";
+
+#[derive(Debug, Default)]
+pub struct CliArgs {
+    pub path: Option<PathBuf>,
+    pub threads: Option<ThreadCount>,
+    pub detail_threshold: Option<u64>,
+    pub min_fraction: Option<MinFraction>,
+    pub max_depth: Option<MaxDepth>,
+    pub file_threshold: Option<u64>,
+    pub largest_files: Option<usize>,
+    pub size_mode: Option<SizeMode>,
+    pub one_file_system: bool,
+    pub symlinks: Option<SymlinkPolicy>,
+    pub exclude: Vec<String>,
+    pub include: Vec<String>,
+    pub ignore_mode: Option<IgnoreMode>,
+    pub config: Option<PathBuf>,
+    pub snapshot: Option<PathBuf>,
+    pub import: Option<PathBuf>,
+    pub export: Option<ExportTarget>,
+}
+
+pub fn parse_args() -> CliArgs {
+    match parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<CliArgs, String> {
+    let mut cli = CliArgs::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-t" | "--threads" => cli.threads = Some(parse_value(&arg, args.next())?),
+            "-s" | "--size-mode" => cli.size_mode = Some(parse_value(&arg, args.next())?),
+            "-x" | "--one-file-system" => cli.one_file_system = true,
+            "-L" | "--symlinks" => cli.symlinks = Some(parse_value(&arg, args.next())?),
+            "-e" | "--exclude" => cli.exclude.push(parse_value(&arg, args.next())?),
+            "-i" | "--include" => cli.include.push(parse_value(&arg, args.next())?),
+            "-g" | "--gitignore" => cli.ignore_mode = Some(parse_value(&arg, args.next())?),
+            "-m" | "--min-size" => cli.detail_threshold = Some(parse_value(&arg, args.next())?),
+            "-f" | "--min-fraction" => cli.min_fraction = Some(parse_value(&arg, args.next())?),
+            "-d" | "--max-depth" => cli.max_depth = Some(parse_value(&arg, args.next())?),
+            "-F" | "--file-size" => cli.file_threshold = Some(parse_value(&arg, args.next())?),
+            "-n" | "--largest" => cli.largest_files = Some(parse_value(&arg, args.next())?),
+            "-o" | "--open" => cli.snapshot = Some(parse_value(&arg, args.next())?),
+            "-I" | "--import" => cli.import = Some(parse_value(&arg, args.next())?),
+            "-E" | "--export" => cli.export = Some(parse_value(&arg, args.next())?),
+            "-c" | "--config" => cli.config = Some(parse_value(&arg, args.next())?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => cli.path = Some(PathBuf::from(arg)),
+        }
+    }
+
+    Ok(cli)
+}
+
+#[inline]
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value for '{}'", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for '{}'", value, flag))
+}
@@ -0,0 +1,95 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{env, fs, process};
+
+use crate::scan::{MaxDepth, MinFraction, ScanOptions, ThreadCount};
+
+const CONFIG_FILE: &str = "folder-scan.conf";
+
+// `$XDG_CONFIG_HOME/folder-scan.conf` (or `~/.config/...`, `%APPDATA%\...` on windows)
+pub fn default_path() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+
+    dir.map(|dir| dir.join(CONFIG_FILE))
+}
+
+// apply the config file to `options`, a missing default config is not an error
+pub fn load_into(options: &mut ScanOptions, explicit: Option<&Path>) {
+    let path = match explicit {
+        Some(path) => path.to_path_buf(),
+        None => match default_path() {
+            Some(path) if path.exists() => path,
+            _ => return,
+        },
+    };
+
+    let result = fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| apply(options, &text));
+
+    if let Err(e) = result {
+        eprintln!("error: {}: {}", path.display(), e);
+        process::exit(2);
+    }
+}
+
+// `key = value` lines, `#` starts a comment, `exclude`/`include` may repeat
+fn apply(options: &mut ScanOptions, text: &str) -> Result<(), String> {
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line_error = |e: String| format!("line {}: {}", index + 1, e);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| line_error(format!("expected 'key = value', got '{}'", line)))?;
+        let value = value.trim();
+
+        match key.trim() {
+            "threads" => {
+                options.threads = parse_value::<ThreadCount>(key, value)
+                    .map_err(line_error)?
+                    .0
+            }
+            "min-size" => options.detail_threshold = parse_value(key, value).map_err(line_error)?,
+            "min-fraction" => {
+                options.min_fraction = parse_value::<MinFraction>(key, value)
+                    .map_err(line_error)?
+                    .0
+            }
+            "max-depth" => {
+                options.max_depth = parse_value::<MaxDepth>(key, value).map_err(line_error)?.0
+            }
+            "file-size" => options.file_threshold = parse_value(key, value).map_err(line_error)?,
+            "largest-files" => {
+                options.largest_files = parse_value(key, value).map_err(line_error)?
+            }
+            "size-mode" => options.size_mode = parse_value(key, value).map_err(line_error)?,
+            "one-file-system" => {
+                options.one_file_system = parse_value(key, value).map_err(line_error)?
+            }
+            "symlinks" => options.symlinks = parse_value(key, value).map_err(line_error)?,
+            "exclude" => options.exclude.push(value.to_string()),
+            "include" => options.include.push(value.to_string()),
+            "gitignore" => options.ignore_mode = parse_value(key, value).map_err(line_error)?,
+            other => return Err(line_error(format!("unknown key '{}'", other))),
+        }
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for '{}'", value, key.trim()))
+}
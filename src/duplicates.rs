@@ -0,0 +1,467 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::hash::{DefaultHasher, Hasher};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::filter::{FilterMatch, ScanFilter};
+use crate::ignore::{self, IgnoreMode, IgnoreStack};
+use crate::pool;
+use crate::scan::{
+    CancelToken, ScanCancelled, ScanOptions, ScanProgress, SymlinkPolicy, device_id, file_id,
+    follow_symlink, hard_link_id,
+};
+
+const PARTIAL_BYTES: u64 = 16 * 1024; // read from both ends of a file before hashing all of it
+const BUFFER_SIZE: usize = 128 * 1024;
+const SECOND_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+// files with identical contents
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub size: u64, // of a single copy
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    // space freed by keeping only one copy
+    #[inline(always)]
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.paths.len() as u64).saturating_sub(1)
+    }
+}
+
+// 128 bits from two differently seeded sip hashers, std has nothing wider,
+// files that end up with the same digest are still compared byte by byte
+type Digest = (u64, u64);
+
+struct ContentHasher(DefaultHasher, DefaultHasher);
+
+impl ContentHasher {
+    fn new() -> Self {
+        let mut second = DefaultHasher::new();
+        second.write_u64(SECOND_SEED);
+        Self(DefaultHasher::new(), second)
+    }
+
+    #[inline]
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.write(bytes);
+        self.1.write(bytes);
+    }
+
+    #[inline]
+    fn finish(&self) -> Digest {
+        (self.0.finish(), self.1.finish())
+    }
+}
+
+// a file that still has at least one possible copy
+struct Candidate {
+    key: Digest, // what it matched the other files of its group on so far
+    size: u64,
+    path: PathBuf,
+}
+
+// a directory left to list, with the ignore state the scan had for it
+struct ListJob {
+    path: PathBuf,
+    ignores: IgnoreStack,
+    ignored: bool,
+}
+
+// group files by size, then by a hash of their ends, then by a hash of everything,
+// then by their actual bytes
+pub fn find_duplicates<F>(
+    root_path: &Path,
+    options: &ScanOptions,
+    cancel: &CancelToken,
+    mut progress_callback: Option<F>,
+) -> Result<Vec<DuplicateGroup>, Box<dyn std::error::Error>>
+where
+    F: FnMut(&ScanProgress),
+{
+    let mut progress = ScanProgress {
+        message: "Finding duplicates: listing files".to_string(),
+        ..ScanProgress::default()
+    };
+    if let Some(callback) = &mut progress_callback {
+        callback(&progress);
+    }
+
+    let files = list_files(root_path, options, cancel)?;
+    let candidates = files
+        .into_iter()
+        .map(|(size, path)| Candidate {
+            key: (size, 0),
+            size,
+            path,
+        })
+        .collect();
+    let candidates = keep_groups(candidates);
+
+    // small files are already read completely by the partial pass
+    let candidates = hash_candidates(
+        candidates,
+        true,
+        options.threads,
+        cancel,
+        &mut progress,
+        &mut progress_callback,
+    )?;
+    let (whole, partial): (Vec<_>, Vec<_>) = keep_groups(candidates)
+        .into_iter()
+        .partition(|candidate| candidate.size <= PARTIAL_BYTES * 2);
+
+    let mut candidates = hash_candidates(
+        partial,
+        false,
+        options.threads,
+        cancel,
+        &mut progress,
+        &mut progress_callback,
+    )?;
+    candidates.extend(whole);
+
+    let mut digests: HashMap<(u64, Digest), DuplicateGroup> = HashMap::new();
+    for candidate in keep_groups(candidates) {
+        digests
+            .entry((candidate.size, candidate.key))
+            .or_insert_with(|| DuplicateGroup {
+                size: candidate.size,
+                paths: Vec::new(),
+            })
+            .paths
+            .push(candidate.path);
+    }
+
+    progress.message = format!("Finding duplicates: verifying {} groups", digests.len());
+    if let Some(callback) = &mut progress_callback {
+        callback(&progress);
+    }
+
+    // a shared digest only makes a collision unlikely, the bytes have to match too
+    let groups = Mutex::new(Vec::new());
+    pool::run(
+        options.threads,
+        digests.into_values().collect(),
+        |group: DuplicateGroup, _| {
+            let verified = split_identical(group.paths, cancel)
+                .into_iter()
+                .filter(|paths| paths.len() > 1)
+                .map(|paths| DuplicateGroup {
+                    size: group.size,
+                    paths,
+                });
+            groups.lock().unwrap().extend(verified);
+        },
+    );
+    if cancel.is_cancelled() {
+        return Err(Box::new(ScanCancelled));
+    }
+
+    let mut groups = groups.into_inner().unwrap();
+    for group in &mut groups {
+        group.paths.sort();
+    }
+    groups.sort_by(|a, b| b.wasted().cmp(&a.wasted()).then(a.paths.cmp(&b.paths)));
+
+    Ok(groups)
+}
+
+// drop every candidate that doesn't share its size and key with another one
+fn keep_groups(candidates: Vec<Candidate>) -> Vec<Candidate> {
+    let mut counts: HashMap<(u64, Digest), usize> = HashMap::new();
+    for candidate in &candidates {
+        *counts.entry((candidate.size, candidate.key)).or_default() += 1;
+    }
+
+    candidates
+        .into_iter()
+        .filter(|candidate| counts[&(candidate.size, candidate.key)] > 1)
+        .collect()
+}
+
+// non-empty regular files below the root, every inode only once, skipping
+// what the scan skipped (filters, ignore rules, links it didn't follow)
+fn list_files(
+    root_path: &Path,
+    options: &ScanOptions,
+    cancel: &CancelToken,
+) -> Result<Vec<(u64, PathBuf)>, Box<dyn std::error::Error>> {
+    let filter = ScanFilter::new(&options.exclude, &options.include)?;
+    let root = fs::canonicalize(root_path).unwrap_or_else(|_| root_path.to_path_buf());
+    let root_device = fs::metadata(root_path).map_or(0, |metadata| device_id(&metadata));
+    let files = Mutex::new(Vec::new());
+    let inodes = Mutex::new(HashSet::new());
+    let visited = Mutex::new(HashSet::new()); // directories, breaks symlink loops
+
+    if let Ok(metadata) = fs::metadata(root_path)
+        && let Some(id) = file_id(root_path, &metadata)
+    {
+        visited.lock().unwrap().insert(id);
+    }
+
+    let first_job = ListJob {
+        path: root_path.to_path_buf(),
+        ignores: IgnoreStack::default(),
+        ignored: false,
+    };
+    pool::run(options.threads, vec![first_job], |job: ListJob, worker| {
+        if cancel.is_cancelled() {
+            return;
+        }
+        let Ok(entries) = fs::read_dir(&job.path) else {
+            return;
+        };
+
+        let ignores = if options.ignore_mode == IgnoreMode::Off || job.ignored {
+            job.ignores
+        } else {
+            job.ignores.enter(&job.path)
+        };
+
+        let mut found = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(mut metadata) = entry.metadata() else {
+                continue;
+            };
+
+            // a link that isn't followed isn't a copy
+            if metadata.file_type().is_symlink() {
+                match follow_symlink(options.symlinks, &root, &path) {
+                    Some(target) => metadata = target,
+                    None => continue,
+                }
+            }
+            let is_dir = metadata.is_dir();
+
+            if filter.check(root_path, &path, is_dir) != FilterMatch::Keep {
+                continue;
+            }
+            let ignored = match options.ignore_mode {
+                IgnoreMode::Off => false,
+                IgnoreMode::NotIgnored if is_dir && ignore::is_git_dir(&entry.file_name()) => {
+                    continue;
+                }
+                _ => job.ignored || ignores.is_ignored(&path, is_dir),
+            };
+            let counted = options.ignore_mode.keeps(ignored);
+
+            if is_dir {
+                if ignored && !counted {
+                    continue;
+                }
+                if options.one_file_system && device_id(&metadata) != root_device {
+                    continue;
+                }
+                if options.symlinks != SymlinkPolicy::Never
+                    && let Some(id) = file_id(&path, &metadata)
+                    && !visited.lock().unwrap().insert(id)
+                {
+                    continue;
+                }
+                worker.spawn(ListJob {
+                    path,
+                    ignores: ignores.clone(),
+                    ignored,
+                });
+            } else if metadata.is_file() && metadata.len() > 0 && counted {
+                // hard links share their blocks and a followed link may lead to a
+                // file listed anyway, neither wastes anything
+                let inode = if options.symlinks == SymlinkPolicy::Never {
+                    hard_link_id(&metadata)
+                } else {
+                    file_id(&path, &metadata)
+                };
+                if let Some(inode) = inode
+                    && !inodes.lock().unwrap().insert(inode)
+                {
+                    continue;
+                }
+                found.push((metadata.len(), path));
+            }
+        }
+
+        files.lock().unwrap().extend(found);
+    });
+
+    if cancel.is_cancelled() {
+        return Err(Box::new(ScanCancelled));
+    }
+
+    Ok(files.into_inner().unwrap())
+}
+
+// replace the key of every candidate by a hash of its ends (`partial`) or its contents
+fn hash_candidates<F>(
+    candidates: Vec<Candidate>,
+    partial: bool,
+    threads: usize,
+    cancel: &CancelToken,
+    progress: &mut ScanProgress,
+    progress_callback: &mut Option<F>,
+) -> Result<Vec<Candidate>, Box<dyn std::error::Error>>
+where
+    F: FnMut(&ScanProgress),
+{
+    let read_size = |size: u64| {
+        if partial {
+            size.min(PARTIAL_BYTES * 2)
+        } else {
+            size
+        }
+    };
+    let total_bytes: u64 = candidates.iter().map(|c| read_size(c.size)).sum();
+    let total_files = candidates.len();
+    let stage = if partial { "comparing" } else { "hashing" };
+
+    let (tx, rx) = mpsc::channel();
+    let mut hashed = Vec::with_capacity(total_files);
+    progress.entries = 0;
+    progress.bytes = 0;
+    progress.percentage = 0;
+
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let tx = tx;
+            pool::run(threads, candidates, |candidate: Candidate, _| {
+                if cancel.is_cancelled() {
+                    return;
+                }
+
+                // unreadable files can't be compared, they just drop out
+                let digest = hash_file(&candidate.path, candidate.size, partial);
+                tx.send((digest, candidate)).ok();
+            });
+        });
+
+        let start_time = Instant::now();
+        let mut last_progress = start_time;
+        loop {
+            match rx.recv_timeout(PROGRESS_INTERVAL) {
+                Ok((digest, candidate)) => {
+                    progress.entries += 1;
+                    progress.bytes += read_size(candidate.size);
+                    if let Ok(key) = digest {
+                        hashed.push(Candidate { key, ..candidate });
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+
+            if let Some(callback) = progress_callback
+                && last_progress.elapsed() >= PROGRESS_INTERVAL
+            {
+                let seconds = start_time.elapsed().as_secs_f64();
+                let done = progress.bytes as f64 / total_bytes.max(1) as f64;
+                progress.percentage = (done * 99.0) as i32;
+                progress.entries_per_second = progress.entries as f64 / seconds.max(f64::EPSILON);
+                progress.eta =
+                    (done > 0.0).then(|| Duration::from_secs_f64(seconds / done * (1.0 - done)));
+                progress.message = format!(
+                    "Finding duplicates: {} {} of {} files",
+                    stage, progress.entries, total_files
+                );
+                callback(progress);
+                last_progress = Instant::now();
+            }
+        }
+    });
+
+    if cancel.is_cancelled() {
+        return Err(Box::new(ScanCancelled));
+    }
+
+    Ok(hashed)
+}
+
+fn hash_file(path: &Path, size: u64, partial: bool) -> io::Result<Digest> {
+    let mut file = File::open(path)?;
+    let mut hasher = ContentHasher::new();
+
+    if partial && size > PARTIAL_BYTES * 2 {
+        // headers of installers and archives often match, their ends rarely do
+        let mut head = vec![0; PARTIAL_BYTES as usize];
+        file.read_exact(&mut head)?;
+        hasher.update(&head);
+        file.seek(SeekFrom::End(-(PARTIAL_BYTES as i64)))?;
+        file.read_exact(&mut head)?;
+        hasher.update(&head);
+    } else {
+        let mut buffer = vec![0; BUFFER_SIZE];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+    }
+
+    // the file changed while we were looking at it
+    if file.metadata()?.len() != size {
+        return Err(io::Error::other("file size changed"));
+    }
+
+    Ok(hasher.finish())
+}
+
+// split files of the same size into sets with identical contents, unreadable ones drop out
+fn split_identical(paths: Vec<PathBuf>, cancel: &CancelToken) -> Vec<Vec<PathBuf>> {
+    let mut sets: Vec<Vec<PathBuf>> = Vec::new();
+
+    for path in paths {
+        if cancel.is_cancelled() {
+            break;
+        }
+        // almost always the first set, anything else is a hash collision
+        match sets
+            .iter_mut()
+            .find(|set| same_contents(&set[0], &path, cancel).unwrap_or(false))
+        {
+            Some(set) => set.push(path),
+            None if File::open(&path).is_ok() => sets.push(vec![path]),
+            None => {}
+        }
+    }
+
+    sets
+}
+
+fn same_contents(a: &Path, b: &Path, cancel: &CancelToken) -> io::Result<bool> {
+    let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+    let mut buffer_a = vec![0; BUFFER_SIZE];
+    let mut buffer_b = vec![0; BUFFER_SIZE];
+
+    loop {
+        if cancel.is_cancelled() {
+            return Ok(false);
+        }
+        let read = read_full(&mut a, &mut buffer_a)?;
+        if read != read_full(&mut b, &mut buffer_b)? || buffer_a[..read] != buffer_b[..read] {
+            return Ok(false);
+        }
+        if read == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+// fill `buffer` unless the file ends first, `read` alone may stop short anywhere
+fn read_full(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match file.read(&mut buffer[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
@@ -0,0 +1,409 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::folder::{FolderNode, NodeKind};
+
+// ncdu export format version (major, minor)
+const NCDU_VERSION: (u32, u32) = (1, 2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json, // nested objects, one per node
+    Csv,  // one line per node
+    Ncdu, // can be opened with `ncdu -f`
+}
+
+impl ExportFormat {
+    #[inline]
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json | Self::Ncdu => "json",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json => write!(f, "json"),
+            Self::Csv => write!(f, "csv"),
+            Self::Ncdu => write!(f, "ncdu"),
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "ncdu" => Ok(Self::Ncdu),
+            _ => Err(format!("unknown export format '{}'", s)),
+        }
+    }
+}
+
+// `FORMAT:FILE` as given on the command line
+#[derive(Debug, Clone)]
+pub struct ExportTarget {
+    pub format: ExportFormat,
+    pub path: PathBuf,
+}
+
+impl FromStr for ExportTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, path) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected 'FORMAT:FILE', got '{}'", s))?;
+        if path.is_empty() {
+            return Err(format!("missing file in '{}'", s));
+        }
+
+        Ok(Self {
+            format: format.parse()?,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+pub fn export_to_file(root: &FolderNode, format: ExportFormat, path: &Path) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    match format {
+        ExportFormat::Json => write_json(&mut out, root)?,
+        ExportFormat::Csv => write_csv(&mut out, root)?,
+        ExportFormat::Ncdu => write_ncdu(&mut out, root)?,
+    }
+    out.flush()
+}
+
+fn write_json(out: &mut impl Write, root: &FolderNode) -> io::Result<()> {
+    write_json_node(out, root)?;
+    writeln!(out)
+}
+
+fn write_json_node(out: &mut impl Write, node: &FolderNode) -> io::Result<()> {
+    write!(
+        out,
+        "{{\"name\":{},\"path\":{},\"kind\":\"{}\",\"size\":{},\"allocated_size\":{},\
+         \"files\":{},\"folders\":{},\"other\":{}",
+        json_string(&node.name),
+        json_string(&node.path.to_string_lossy()),
+        kind_name(node),
+        node.size,
+        node.allocated_size,
+        node.file_count,
+        node.dir_count,
+        node.other_count
+    )?;
+    if let Some(mtime) = node.times.newest_mtime {
+        write!(out, ",\"modified\":{}", unix_seconds(mtime))?;
+    }
+    if node.is_small_items() {
+        write!(out, ",\"merged_items\":{}", node.small_items)?;
+    }
+
+    if node.kind == NodeKind::Directory && !node.is_small_items() {
+        write!(out, ",\"children\":[")?;
+        for (i, child) in node.children.iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            write_json_node(out, child)?;
+        }
+        write!(out, "]")?;
+    }
+
+    write!(out, "}}")
+}
+
+fn write_csv(out: &mut impl Write, root: &FolderNode) -> io::Result<()> {
+    writeln!(
+        out,
+        "path,depth,kind,size,allocated_size,files,folders,other"
+    )?;
+    write_csv_node(out, root, 0)
+}
+
+fn write_csv_node(out: &mut impl Write, node: &FolderNode, depth: usize) -> io::Result<()> {
+    // merged small items share their parent's path, the name tells them apart
+    let path = if node.is_small_items() {
+        node.path.join(&node.name)
+    } else {
+        node.path.clone()
+    };
+
+    writeln!(
+        out,
+        "{},{},{},{},{},{},{},{}",
+        csv_field(&path.to_string_lossy()),
+        depth,
+        kind_name(node),
+        node.size,
+        node.allocated_size,
+        node.file_count,
+        node.dir_count,
+        node.other_count
+    )?;
+
+    node.children
+        .iter()
+        .try_for_each(|child| write_csv_node(out, child, depth + 1))
+}
+
+// `[major, minor, metadata, root]`, directories are arrays of their info followed by their entries
+fn write_ncdu(out: &mut impl Write, root: &FolderNode) -> io::Result<()> {
+    write!(
+        out,
+        "[{},{},{{\"progname\":\"folder-scan\",\"progver\":\"{}\",\"timestamp\":{}}},",
+        NCDU_VERSION.0,
+        NCDU_VERSION.1,
+        env!("CARGO_PKG_VERSION"),
+        unix_seconds(SystemTime::now())
+    )?;
+    write_ncdu_node(out, root, true)?;
+    writeln!(out, "]")
+}
+
+fn write_ncdu_node(out: &mut impl Write, node: &FolderNode, is_root: bool) -> io::Result<()> {
+    // ncdu wants the full path for the root only
+    let name = if is_root {
+        node.path.to_string_lossy().to_string()
+    } else {
+        node.name.clone()
+    };
+
+    if node.kind != NodeKind::Directory {
+        write!(
+            out,
+            "{{\"name\":{},\"asize\":{},\"dsize\":{}",
+            json_string(&name),
+            node.size,
+            node.allocated_size
+        )?;
+        if matches!(node.kind, NodeKind::Symlink | NodeKind::Other) {
+            write!(out, ",\"notreg\":true")?;
+        }
+        return write!(out, "}}");
+    }
+
+    // ncdu adds up the entries itself, a directory's own size is its own blocks
+    // plus the files and merged items that didn't get a node of their own
+    let entries: Vec<&FolderNode> = node
+        .children
+        .iter()
+        .filter(|child| !child.is_small_items())
+        .collect();
+    let (size, allocated_size) = entries.iter().fold(
+        (node.size, node.allocated_size),
+        |(size, allocated_size), child| {
+            (
+                size.saturating_sub(child.size),
+                allocated_size.saturating_sub(child.allocated_size),
+            )
+        },
+    );
+
+    write!(
+        out,
+        "[{{\"name\":{},\"asize\":{},\"dsize\":{}",
+        json_string(&name),
+        size,
+        allocated_size
+    )?;
+    if node.skipped {
+        write!(out, ",\"excluded\":\"othfs\"")?;
+    }
+    if node.incomplete {
+        write!(out, ",\"read_error\":true")?;
+    }
+    write!(out, "}}")?;
+
+    for child in entries {
+        write!(out, ",")?;
+        write_ncdu_node(out, child, false)?;
+    }
+
+    write!(out, "]")
+}
+
+#[inline]
+fn kind_name(node: &FolderNode) -> &'static str {
+    match node.kind {
+        _ if node.is_small_items() => "merged",
+        NodeKind::Directory => "directory",
+        NodeKind::File => "file",
+        NodeKind::Symlink => "symlink",
+        NodeKind::Other => "other",
+    }
+}
+
+#[inline]
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+fn json_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// quoted only when needed, quotes inside are doubled
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::import_data;
+    use crate::scan::ScanOptions;
+
+    fn node(name: &str, path: &str, kind: NodeKind, size: u64, allocated_size: u64) -> FolderNode {
+        let mut node = FolderNode::new(name.to_string(), PathBuf::from(path), size, allocated_size);
+        node.kind = kind;
+        node
+    }
+
+    // /data holds 5 files of 10 bytes without nodes, a big file, a folder and merged items
+    fn sample() -> FolderNode {
+        let mut root = node("data", "/data", NodeKind::Directory, 0, 4096);
+        root.file_count = 5;
+        root.size = 50;
+        root.allocated_size += 5 * 4096;
+
+        let big = node(
+            "big.iso",
+            "/data/big.iso",
+            NodeKind::File,
+            3_000_000,
+            3_002_368,
+        );
+        let mut sub = node("sub", "/data/sub", NodeKind::Directory, 0, 4096);
+        sub.children.push(node(
+            "big2",
+            "/data/sub/big2",
+            NodeKind::File,
+            2_000_000,
+            2_002_944,
+        ));
+        sub.size += 2_000_000;
+        sub.allocated_size += 2_002_944;
+        let mut merged = node(
+            "(2 smaller items)",
+            "/data",
+            NodeKind::Directory,
+            20,
+            12_288,
+        );
+        merged.small_items = 2;
+
+        for child in [big, sub, merged] {
+            root.size += child.size;
+            root.allocated_size += child.allocated_size;
+            root.children.push(child);
+        }
+        root
+    }
+
+    fn export(root: &FolderNode, format: ExportFormat) -> String {
+        let mut out = Vec::new();
+        match format {
+            ExportFormat::Json => write_json(&mut out, root).unwrap(),
+            ExportFormat::Csv => write_csv(&mut out, root).unwrap(),
+            ExportFormat::Ncdu => write_ncdu(&mut out, root).unwrap(),
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("plain"), "\"plain\"");
+        assert_eq!(json_string("a\"b\\c"), "\"a\\\"b\\\\c\"");
+        assert_eq!(json_string("\n\r\t"), "\"\\n\\r\\t\"");
+        assert_eq!(json_string("\u{1}é"), "\"\\u0001é\"");
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("/data/a b"), "/data/a b");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn csv_names_merged_items_by_their_parent() {
+        let text = export(&sample(), ExportFormat::Csv);
+        let mut lines = text.lines();
+        assert_eq!(
+            lines.next(),
+            Some("path,depth,kind,size,allocated_size,files,folders,other")
+        );
+        assert!(text.contains("\n/data/(2 smaller items),1,merged,20,12288,0,0,0\n"));
+        assert!(text.contains("\n/data/sub/big2,2,file,2000000,2002944,0,0,0\n"));
+    }
+
+    #[test]
+    fn json_keeps_merged_items_as_leaves() {
+        let text = export(&sample(), ExportFormat::Json);
+        assert!(text.starts_with("{\"name\":\"data\",\"path\":\"/data\",\"kind\":\"directory\""));
+        assert!(text.contains("\"kind\":\"merged\",\"size\":20"));
+        assert!(text.contains("\"merged_items\":2}"));
+    }
+
+    // ncdu has no counts, files without a node are only left in their folder's
+    // own size, so only the sizes survive a round trip
+    #[test]
+    fn ncdu_export_keeps_the_totals() {
+        let root = sample();
+        let text = export(&root, ExportFormat::Ncdu);
+        assert!(!text.contains("smaller items"));
+        assert!(text.contains("{\"name\":\"sub\",\"asize\":0,\"dsize\":4096}"));
+
+        let options = ScanOptions {
+            detail_threshold: 0,
+            min_fraction: 0.0,
+            file_threshold: 0,
+            ..ScanOptions::default()
+        };
+        let imported = import_data(text.as_bytes(), UNIX_EPOCH, &options).unwrap();
+        let imported = imported.result.root;
+        assert_eq!(imported.path, root.path);
+        assert_eq!(imported.size, root.size);
+        assert_eq!(imported.allocated_size, root.allocated_size);
+
+        let sub = imported
+            .children
+            .iter()
+            .find(|child| child.name == "sub")
+            .unwrap();
+        assert_eq!(sub.size, 2_000_000);
+        assert_eq!(sub.allocated_size, 4096 + 2_002_944);
+    }
+}
@@ -0,0 +1,377 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+// a single glob pattern like `*.o`, `node_modules/` or `**/target`
+#[derive(Debug, Clone)]
+pub struct Glob {
+    tokens: Vec<Token>,
+    name_only: bool, // no `/` in the pattern, matched against the entry name
+    dir_only: bool,  // trailing `/`, only matches directories
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Char(char),
+    Any,        // `?`
+    Star,       // `*`, never crosses a `/`
+    DoubleStar, // `**`, crosses any number of `/`
+    AnyDirs,    // `**/`, zero or more leading directories
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let trimmed = pattern.strip_suffix('/').unwrap_or(pattern);
+        if trimmed.is_empty() {
+            return Err(format!("empty pattern '{}'", pattern));
+        }
+
+        let mut tokens = Vec::new();
+        let mut chars = trimmed.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '?' => Token::Any,
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        Token::AnyDirs
+                    } else {
+                        Token::DoubleStar
+                    }
+                }
+                '*' => Token::Star,
+                '[' => parse_class(&mut chars)
+                    .ok_or_else(|| format!("unclosed '[' in pattern '{}'", pattern))?,
+                '\\' => Token::Char(chars.next().unwrap_or('\\')),
+                c => Token::Char(c),
+            };
+            tokens.push(token);
+        }
+
+        Ok(Self {
+            tokens,
+            name_only: !trimmed.contains('/'),
+            dir_only: trimmed.len() < pattern.len(),
+        })
+    }
+
+    // match against the whole relative path even without a `/` in the pattern
+    #[inline]
+    pub fn anchored(mut self) -> Self {
+        self.name_only = false;
+        self
+    }
+
+    // `relative` is the path below the scanned folder, `full` the whole path
+    pub fn matches(&self, relative: &str, full: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        if self.name_only {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            return self.matches_text(name);
+        }
+
+        self.matches_text(relative) || self.matches_text(full)
+    }
+
+    #[inline]
+    fn matches_text(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        Matcher {
+            tokens: &self.tokens,
+            text: &text,
+            failed: HashSet::new(),
+        }
+        .matches(0, 0)
+    }
+}
+
+fn parse_class(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Option<Token> {
+    let negated = matches!(chars.peek(), Some('!' | '^'));
+    if negated {
+        chars.next();
+    }
+
+    let mut ranges = Vec::new();
+    let mut first = true;
+
+    loop {
+        let c = chars.next()?;
+        // a `]` right after the opening bracket is a literal
+        if c == ']' && !first {
+            break;
+        }
+        first = false;
+
+        if chars.peek() == Some(&'-') {
+            chars.next();
+            match chars.next()? {
+                ']' => {
+                    ranges.push((c, c));
+                    ranges.push(('-', '-'));
+                    break;
+                }
+                end => ranges.push((c, end)),
+            }
+        } else {
+            ranges.push((c, c));
+        }
+    }
+
+    Some(Token::Class { negated, ranges })
+}
+
+// remembers where a wildcard already failed, so patterns like `**a**a**b`
+// can't backtrack exponentially
+struct Matcher<'a> {
+    tokens: &'a [Token],
+    text: &'a [char],
+    failed: HashSet<(usize, usize)>, // (token, position)
+}
+
+impl Matcher<'_> {
+    fn matches(&mut self, token: usize, pos: usize) -> bool {
+        let (tokens, text) = (self.tokens, self.text);
+        let Some(current) = tokens.get(token) else {
+            return pos == text.len();
+        };
+
+        match current {
+            Token::Char(c) => text.get(pos) == Some(c) && self.matches(token + 1, pos + 1),
+            Token::Any => {
+                matches!(text.get(pos), Some(c) if *c != '/') && self.matches(token + 1, pos + 1)
+            }
+            Token::Class { negated, ranges } => match text.get(pos) {
+                Some(c) if *c != '/' => {
+                    let in_class = ranges.iter().any(|(start, end)| (start..=end).contains(&c));
+                    in_class != *negated && self.matches(token + 1, pos + 1)
+                }
+                _ => false,
+            },
+            _ if self.failed.contains(&(token, pos)) => false,
+            wildcard => {
+                let found = match wildcard {
+                    Token::Star => {
+                        let segment_end = text[pos..]
+                            .iter()
+                            .position(|c| *c == '/')
+                            .map_or(text.len(), |end| pos + end);
+                        (pos..=segment_end).any(|i| self.matches(token + 1, i))
+                    }
+                    Token::DoubleStar => (pos..=text.len()).any(|i| self.matches(token + 1, i)),
+                    _ => {
+                        self.matches(token + 1, pos)
+                            || (pos..text.len())
+                                .any(|i| text[i] == '/' && self.matches(token + 1, i + 1))
+                    }
+                };
+                if !found {
+                    self.failed.insert((token, pos));
+                }
+                found
+            }
+        }
+    }
+}
+
+// outcome of checking an entry against a `ScanFilter`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterMatch {
+    Keep,
+    Excluded,    // matched an exclude pattern
+    NotIncluded, // file that matched none of the include patterns
+}
+
+// include/exclude patterns applied while walking
+#[derive(Debug, Clone, Default)]
+pub struct ScanFilter {
+    exclude: Vec<Glob>,
+    include: Vec<Glob>,
+}
+
+impl ScanFilter {
+    pub fn new(exclude: &[String], include: &[String]) -> Result<Self, String> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| Glob::new(pattern))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(Self {
+            exclude: compile(exclude)?,
+            include: compile(include)?,
+        })
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.exclude.is_empty() && self.include.is_empty()
+    }
+
+    // include patterns only apply to files, directories are always walked
+    pub fn check(&self, root: &Path, path: &Path, is_dir: bool) -> FilterMatch {
+        if self.is_empty() {
+            return FilterMatch::Keep;
+        }
+
+        let full = normalize(path);
+        let relative = path
+            .strip_prefix(root)
+            .map_or_else(|_| full.clone(), normalize);
+
+        if self
+            .exclude
+            .iter()
+            .any(|glob| glob.matches(&relative, &full, is_dir))
+        {
+            return FilterMatch::Excluded;
+        }
+
+        if !is_dir
+            && !self.include.is_empty()
+            && !self
+                .include
+                .iter()
+                .any(|glob| glob.matches(&relative, &full, is_dir))
+        {
+            return FilterMatch::NotIncluded;
+        }
+
+        FilterMatch::Keep
+    }
+}
+
+// patterns always use `/` as the separator
+#[inline]
+fn normalize(path: &Path) -> String {
+    let text = path.to_string_lossy();
+    if std::path::MAIN_SEPARATOR == '/' {
+        text.into_owned()
+    } else {
+        text.replace(std::path::MAIN_SEPARATOR, "/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn matches(pattern: &str, relative: &str, is_dir: bool) -> bool {
+        Glob::new(pattern)
+            .unwrap()
+            .matches(relative, &format!("/data/{}", relative), is_dir)
+    }
+
+    #[test]
+    fn name_patterns_match_in_any_directory() {
+        assert!(matches("*.o", "main.o", false));
+        assert!(matches("*.o", "src/deep/main.o", false));
+        assert!(!matches("*.o", "main.oo", false));
+        assert!(!matches("*.o", "main.o/readme", false));
+        assert!(matches("?.txt", "a/b.txt", false));
+        assert!(!matches("?.txt", "a/bc.txt", false));
+    }
+
+    #[test]
+    fn star_stays_inside_a_segment() {
+        assert!(matches("src/*.rs", "src/main.rs", false));
+        assert!(!matches("src/*.rs", "src/bin/main.rs", false));
+        assert!(matches("src/**.rs", "src/bin/main.rs", false));
+    }
+
+    #[test]
+    fn double_star_backtracks() {
+        assert!(matches("**/target", "target", true));
+        assert!(matches("**/target", "a/b/target", true));
+        assert!(!matches("**/target", "a/b/target2", true));
+        assert!(matches("a/**/b", "a/b", true));
+        assert!(matches("a/**/b", "a/x/y/b", true));
+        assert!(matches("a/**/b/*.c", "a/b/x/b/y.c", false));
+        assert!(!matches("a/**/b/*.c", "a/b/x/b/y/z.c", false));
+    }
+
+    #[test]
+    fn slashes_anchor_to_the_scanned_folder() {
+        assert!(matches("build/out", "build/out", true));
+        assert!(!matches("build/out", "x/build/out", true));
+        assert!(matches("out", "x/build/out", true));
+        assert!(
+            Glob::new("out")
+                .unwrap()
+                .anchored()
+                .matches("out", "out", true)
+        );
+        assert!(
+            !Glob::new("out")
+                .unwrap()
+                .anchored()
+                .matches("x/out", "x/out", true)
+        );
+    }
+
+    #[test]
+    fn full_paths_match_too() {
+        assert!(matches("/data/cache", "cache", true));
+        assert!(!matches("/other/cache", "cache", true));
+    }
+
+    #[test]
+    fn trailing_slash_only_matches_directories() {
+        assert!(matches("node_modules/", "web/node_modules", true));
+        assert!(!matches("node_modules/", "web/node_modules", false));
+    }
+
+    #[test]
+    fn classes_and_escapes() {
+        assert!(matches("[abc].txt", "b.txt", false));
+        assert!(!matches("[!abc].txt", "b.txt", false));
+        assert!(matches("[a-c]x", "bx", false));
+        assert!(matches("[]]", "]", false));
+        assert!(matches("[a-]", "-", false));
+        assert!(matches("\\*", "*", false));
+        assert!(!matches("\\*", "a", false));
+        assert!(Glob::new("[abc").is_err());
+        assert!(Glob::new("/").is_err());
+    }
+
+    #[test]
+    fn pathological_patterns_finish_quickly() {
+        let pattern = "**a**a**a**a**a**a**a**a**a**a**b";
+        let text = "a/".repeat(40) + &"a".repeat(200);
+        let start = Instant::now();
+        assert!(!matches(pattern, &text, false));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn filter_excludes_before_including() {
+        let filter = ScanFilter::new(&["*.log".to_string()], &["*.rs".to_string()]).unwrap();
+        let root = Path::new("/data");
+        assert_eq!(
+            filter.check(root, Path::new("/data/a.rs"), false),
+            FilterMatch::Keep
+        );
+        assert_eq!(
+            filter.check(root, Path::new("/data/a.log"), false),
+            FilterMatch::Excluded
+        );
+        assert_eq!(
+            filter.check(root, Path::new("/data/a.txt"), false),
+            FilterMatch::NotIncluded
+        );
+        // directories are walked even when they match no include pattern
+        assert_eq!(
+            filter.check(root, Path::new("/data/src"), true),
+            FilterMatch::Keep
+        );
+    }
+}
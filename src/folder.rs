@@ -1,34 +1,328 @@
-use std::path::PathBuf;
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+use std::{fmt, fs};
+
+use crate::owners::OwnerTable;
+use crate::types::{FileCategory, TypeTable};
+
+// which size folders are measured by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SizeMode {
+    #[default]
+    Apparent, // sum of file lengths
+    Allocated, // blocks actually used on disk (like `du`)
+    Items,     // number of files, folders and other entries
+}
+
+impl SizeMode {
+    pub const ALL: [Self; 3] = [Self::Apparent, Self::Allocated, Self::Items];
+
+    #[inline(always)]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    // the byte size used for thresholds given in bytes
+    #[inline(always)]
+    pub fn bytes(self) -> Self {
+        match self {
+            Self::Items => Self::Apparent,
+            mode => mode,
+        }
+    }
+}
+
+impl fmt::Display for SizeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Apparent => write!(f, "apparent size"),
+            Self::Allocated => write!(f, "disk usage"),
+            Self::Items => write!(f, "item count"),
+        }
+    }
+}
+
+impl FromStr for SizeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "apparent" => Ok(Self::Apparent),
+            "disk" | "allocated" => Ok(Self::Allocated),
+            "items" | "count" => Ok(Self::Items),
+            _ => Err(format!("unknown size mode '{}'", s)),
+        }
+    }
+}
+
+// newest and oldest modification (and last access) seen in a subtree
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimeRange {
+    pub newest_mtime: Option<SystemTime>,
+    pub oldest_mtime: Option<SystemTime>,
+    pub newest_atime: Option<SystemTime>,
+}
+
+impl TimeRange {
+    #[inline]
+    pub fn add(&mut self, metadata: &fs::Metadata) {
+        if let Ok(mtime) = metadata.modified() {
+            self.merge(&TimeRange {
+                newest_mtime: Some(mtime),
+                oldest_mtime: Some(mtime),
+                newest_atime: metadata.accessed().ok(),
+            });
+        }
+    }
+
+    // for files only known by their modification time
+    #[inline]
+    pub fn add_mtime(&mut self, mtime: SystemTime) {
+        self.merge(&TimeRange {
+            newest_mtime: Some(mtime),
+            oldest_mtime: Some(mtime),
+            newest_atime: None,
+        });
+    }
+
+    pub fn merge(&mut self, other: &TimeRange) {
+        // `None` sorts first, so only `max` can be used directly
+        self.newest_mtime = self.newest_mtime.max(other.newest_mtime);
+        self.newest_atime = self.newest_atime.max(other.newest_atime);
+        self.oldest_mtime = match (self.oldest_mtime, other.oldest_mtime) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
+}
+
+// the file category and user taking up most of a subtree, per `SizeMode::index`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Dominant {
+    pub categories: [Option<FileCategory>; 3],
+    pub users: [Option<u32>; 3],
+}
+
+// what a node in the tree stands for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NodeKind {
+    #[default]
+    Directory,
+    File,
+    Symlink, // link that wasn't followed
+    Other,   // sockets, pipes, devices, ... (and imported entries of unknown type)
+}
+
+impl fmt::Display for NodeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Directory => write!(f, "Directory"),
+            Self::File => write!(f, "File"),
+            Self::Symlink => write!(f, "Symlink"),
+            Self::Other => write!(f, "Other"),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct FolderNode {
     pub name: String,
     pub path: PathBuf,
-    pub size: u64,
+    pub kind: NodeKind,
+    pub size: u64,           // apparent size
+    pub allocated_size: u64, // size on disk
+    pub hard_link_size: u64, // part of `size` made up of files with several hard links
+    pub file_count: u64,     // files in the whole subtree
+    pub dir_count: u64,      // folders in the whole subtree (not counting this one)
+    pub other_count: u64,    // symlinks and special files in the whole subtree
+    pub times: TimeRange,    // of the files in the whole subtree
+    pub types: TypeTable,    // usage per file extension in the whole subtree
+    pub owners: OwnerTable,  // usage per uid/gid in the whole subtree
+    pub dominant: Dominant,  // worked out from `types` and `owners` by `cache_dominant`
+    pub incomplete: bool,    // some entries below this folder could not be read
+    pub mount_point: bool,   // lives on another file system than its parent
+    pub skipped: bool,       // mount point that wasn't descended into
+    pub small_items: u64,    // > 0 for the synthetic node standing in for pruned children
     pub children: Vec<FolderNode>,
 }
 
 impl FolderNode {
     #[inline]
-    pub fn new(name: String, path: PathBuf, size: u64) -> Self {
+    pub fn new(name: String, path: PathBuf, size: u64, allocated_size: u64) -> Self {
         Self {
             name,
             path,
+            kind: NodeKind::Directory,
             size,
+            allocated_size,
+            hard_link_size: 0,
+            file_count: 0,
+            dir_count: 0,
+            other_count: 0,
+            times: TimeRange::default(),
+            types: TypeTable::default(),
+            owners: OwnerTable::default(),
+            dominant: Dominant::default(),
+            incomplete: false,
+            mount_point: false,
+            skipped: false,
+            small_items: 0,
             children: Vec::new(),
         }
     }
 
+    #[inline(always)]
+    pub fn size_in(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.size,
+            SizeMode::Allocated => self.allocated_size,
+            SizeMode::Items => self.item_count(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn item_count(&self) -> u64 {
+        self.file_count + self.dir_count + self.other_count
+    }
+
     #[inline(always)]
     pub fn add_child(&mut self, child: FolderNode) {
         self.children.push(child);
     }
 
+    #[inline(always)]
+    pub fn is_small_items(&self) -> bool {
+        self.small_items > 0
+    }
+
+    #[inline(always)]
+    pub fn dominant_category(&self, mode: SizeMode) -> Option<FileCategory> {
+        self.dominant.categories[mode.index()]
+    }
+
+    #[inline(always)]
+    pub fn dominant_user(&self, mode: SizeMode) -> Option<u32> {
+        self.dominant.users[mode.index()]
+    }
+
+    // once the tree is final: remember what dominates every subtree and drop the
+    // tables of merged nodes, their parent already accounts for them
+    pub fn cache_dominant(&mut self) {
+        self.dominant = Dominant {
+            categories: self.types.dominant_categories(),
+            users: SizeMode::ALL.map(|mode| self.owners.dominant_user(mode)),
+        };
+        if self.is_small_items() {
+            self.types = TypeTable::default();
+            self.owners = OwnerTable::default();
+        }
+
+        for child in &mut self.children {
+            child.cache_dominant();
+        }
+    }
+
+    // merge the children matching `prune` into a single "(N smaller items)" child
+    // so the sizes of the remaining children still add up
+    pub fn merge_children(&mut self, mut prune: impl FnMut(&FolderNode) -> bool) {
+        let (small, kept): (Vec<_>, Vec<_>) = self.children.drain(..).partition(|c| prune(c));
+        self.children = kept;
+
+        if small.is_empty() {
+            return;
+        }
+
+        let mut merged = FolderNode::new(String::new(), self.path.clone(), 0, 0);
+        for child in small {
+            merged.size += child.size;
+            merged.allocated_size += child.allocated_size;
+            merged.hard_link_size += child.hard_link_size;
+            merged.file_count += child.file_count;
+            merged.dir_count += child.dir_count;
+            // the merged folders themselves are gone from the tree, they count here now
+            if child.kind == NodeKind::Directory && !child.is_small_items() {
+                merged.dir_count += 1;
+            }
+            merged.other_count += child.other_count;
+            merged.times.merge(&child.times);
+            merged.types.merge(&child.types);
+            merged.owners.merge(&child.owners);
+            merged.incomplete |= child.incomplete;
+            // merging an earlier synthetic node keeps its count
+            merged.small_items += child.small_items.max(1);
+        }
+        merged.name = match merged.small_items {
+            1 => "(1 smaller item)".to_string(),
+            count => format!("({} smaller items)", count),
+        };
+
+        self.children.push(merged);
+    }
+
+    // the node for `path`, merged small items resolve to their parent
+    pub fn find(&self, path: &Path) -> Option<&FolderNode> {
+        if self.path == path {
+            return Some(self);
+        }
+
+        self.children
+            .iter()
+            .filter(|child| path.starts_with(&child.path))
+            .find_map(|child| child.find(path))
+    }
+
     // Sort children by size (largest first)
     pub fn sort_children(&mut self) {
-        self.children.sort_by(|a, b| b.size.cmp(&a.size));
+        self.children.sort_by_key(|child| Reverse(child.size));
         for child in &mut self.children {
             child.sort_children();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folder(name: &str, children: Vec<FolderNode>) -> FolderNode {
+        let mut node = FolderNode::new(name.to_string(), PathBuf::from(name), 0, 0);
+        node.dir_count = children.iter().map(|child| child.dir_count + 1).sum();
+        node.children = children;
+        node
+    }
+
+    #[test]
+    fn merged_folders_are_counted() {
+        let mut root = folder("e1", vec![folder("e2", vec![folder("e3", Vec::new())])]);
+        root.merge_children(|_| true);
+
+        let merged = &root.children[0];
+        assert!(merged.is_small_items());
+        assert_eq!(merged.dir_count, root.dir_count);
+        assert_eq!(merged.dir_count, 2);
+        assert_eq!(merged.item_count(), 2);
+    }
+
+    #[test]
+    fn merging_again_keeps_the_counts() {
+        let mut file = FolderNode::new("f".to_string(), PathBuf::from("f"), 10, 10);
+        file.kind = NodeKind::File;
+        file.file_count = 1;
+
+        let mut root = folder(
+            "root",
+            vec![folder("a", Vec::new()), folder("b", Vec::new())],
+        );
+        root.file_count = 1;
+        root.children.push(file);
+        root.merge_children(|child| child.name == "a");
+        root.merge_children(|_| true);
+
+        let merged = &root.children[0];
+        assert_eq!(merged.small_items, 3);
+        assert_eq!(merged.dir_count, 2);
+        assert_eq!(merged.file_count, 1);
+    }
+}
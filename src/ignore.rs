@@ -0,0 +1,225 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::filter::Glob;
+
+// ignore files read in every directory, later ones take precedence
+const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore"];
+const GIT_DIR: &str = ".git";
+
+// which side of the ignore rules a scan shows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IgnoreMode {
+    #[default]
+    Off,
+    NotIgnored,  // only what git would track
+    IgnoredOnly, // only build outputs, caches, ...
+}
+
+impl IgnoreMode {
+    // whether an entry with the given ignore state is counted
+    #[inline(always)]
+    pub fn keeps(self, ignored: bool) -> bool {
+        match self {
+            Self::Off => true,
+            Self::NotIgnored => !ignored,
+            Self::IgnoredOnly => ignored,
+        }
+    }
+}
+
+impl fmt::Display for IgnoreMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => write!(f, "off"),
+            Self::NotIgnored => write!(f, "tracked"),
+            Self::IgnoredOnly => write!(f, "ignored"),
+        }
+    }
+}
+
+impl FromStr for IgnoreMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "tracked" | "not-ignored" => Ok(Self::NotIgnored),
+            "ignored" => Ok(Self::IgnoredOnly),
+            _ => Err(format!("unknown ignore mode '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct IgnoreRule {
+    glob: Glob,
+    negated: bool, // `!pattern` re-includes what an earlier rule ignored
+}
+
+// the rules of one directory, linked to the rules of its ancestors
+#[derive(Debug)]
+struct IgnoreFrame {
+    base: PathBuf,
+    rules: Vec<IgnoreRule>,
+    parent: Option<Arc<IgnoreFrame>>,
+}
+
+// ignore rules in effect for a directory, cheap to clone into child jobs
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreStack {
+    top: Option<Arc<IgnoreFrame>>,
+}
+
+impl IgnoreStack {
+    // rules for the children of `dir` (its own ignore files on top of ours)
+    pub fn enter(&self, dir: &Path) -> Self {
+        let mut rules = Vec::new();
+
+        // the repository wide excludes have the lowest precedence
+        if let Ok(text) = fs::read_to_string(dir.join(GIT_DIR).join("info").join("exclude")) {
+            rules.extend(text.lines().filter_map(parse_rule));
+        }
+        for file in IGNORE_FILES {
+            if let Ok(text) = fs::read_to_string(dir.join(file)) {
+                rules.extend(text.lines().filter_map(parse_rule));
+            }
+        }
+
+        if rules.is_empty() {
+            return self.clone();
+        }
+
+        Self {
+            top: Some(Arc::new(IgnoreFrame {
+                base: dir.to_path_buf(),
+                rules,
+                parent: self.top.clone(),
+            })),
+        }
+    }
+
+    // deeper ignore files win, inside a file the last matching rule wins
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut frame = self.top.as_deref();
+
+        while let Some(current) = frame {
+            if let Ok(relative) = path.strip_prefix(&current.base) {
+                let relative = relative.to_string_lossy().replace('\\', "/");
+                if let Some(rule) = current
+                    .rules
+                    .iter()
+                    .rev()
+                    .find(|rule| rule.glob.matches(&relative, &relative, is_dir))
+                {
+                    return !rule.negated;
+                }
+            }
+            frame = current.parent.as_deref();
+        }
+
+        false
+    }
+}
+
+// the repository itself isn't tracked by git
+#[inline(always)]
+pub fn is_git_dir(name: &std::ffi::OsStr) -> bool {
+    name == GIT_DIR
+}
+
+fn parse_rule(line: &str) -> Option<IgnoreRule> {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let (negated, pattern) = match line.strip_prefix('!') {
+        Some(pattern) => (true, pattern),
+        None => (false, line),
+    };
+
+    // a leading `/` (or any `/` but a trailing one) ties the pattern to this directory
+    let (anchored, pattern) = match pattern.strip_prefix('/') {
+        Some(pattern) => (true, pattern),
+        None => (false, pattern),
+    };
+
+    let mut glob = Glob::new(pattern).ok()?;
+    if anchored {
+        glob = glob.anchored();
+    }
+
+    Some(IgnoreRule { glob, negated })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack(frames: &[(&str, &str)]) -> IgnoreStack {
+        frames
+            .iter()
+            .fold(IgnoreStack::default(), |stack, (base, text)| IgnoreStack {
+                top: Some(Arc::new(IgnoreFrame {
+                    base: PathBuf::from(base),
+                    rules: text.lines().filter_map(parse_rule).collect(),
+                    parent: stack.top,
+                })),
+            })
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        assert!(parse_rule("").is_none());
+        assert!(parse_rule("   ").is_none());
+        assert!(parse_rule("# target").is_none());
+        assert!(parse_rule("target   ").is_some());
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let rules = stack(&[("/repo", "*.log\n!keep.log")]);
+        assert!(rules.is_ignored(Path::new("/repo/a.log"), false));
+        assert!(!rules.is_ignored(Path::new("/repo/keep.log"), false));
+
+        let rules = stack(&[("/repo", "!keep.log\n*.log")]);
+        assert!(rules.is_ignored(Path::new("/repo/keep.log"), false));
+    }
+
+    #[test]
+    fn deeper_files_take_precedence() {
+        let rules = stack(&[("/repo", "*.log"), ("/repo/logs", "!*.log")]);
+        assert!(rules.is_ignored(Path::new("/repo/a.log"), false));
+        assert!(!rules.is_ignored(Path::new("/repo/logs/a.log"), false));
+        assert!(!rules.is_ignored(Path::new("/repo/a.txt"), false));
+    }
+
+    #[test]
+    fn leading_slash_anchors_to_the_ignore_file() {
+        let rules = stack(&[("/repo", "/build\ncache/")]);
+        assert!(rules.is_ignored(Path::new("/repo/build"), true));
+        assert!(!rules.is_ignored(Path::new("/repo/src/build"), true));
+        assert!(rules.is_ignored(Path::new("/repo/src/cache"), true));
+        assert!(!rules.is_ignored(Path::new("/repo/src/cache"), false));
+    }
+
+    #[test]
+    fn rules_only_apply_below_their_directory() {
+        let rules = stack(&[("/repo/sub", "*.o")]);
+        assert!(rules.is_ignored(Path::new("/repo/sub/a.o"), false));
+        assert!(!rules.is_ignored(Path::new("/repo/a.o"), false));
+    }
+
+    #[test]
+    fn modes_keep_their_side() {
+        assert!(IgnoreMode::Off.keeps(true) && IgnoreMode::Off.keeps(false));
+        assert!(IgnoreMode::NotIgnored.keeps(false) && !IgnoreMode::NotIgnored.keeps(true));
+        assert!(IgnoreMode::IgnoredOnly.keeps(true) && !IgnoreMode::IgnoredOnly.keeps(false));
+        assert_eq!("tracked".parse(), Ok(IgnoreMode::NotIgnored));
+        assert!("git".parse::<IgnoreMode>().is_err());
+    }
+}
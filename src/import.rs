@@ -0,0 +1,822 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::folder::{FolderNode, NodeKind, SizeMode};
+use crate::scan::{
+    LargeFile, LargestFiles, ScanError, ScanErrorKind, ScanOptions, ScanResult, filter_hierarchy,
+    reached_max_depth,
+};
+use crate::snapshot::Snapshot;
+
+const DU_BLOCK_SIZE: u64 = 1024; // `du -k` counts kilobytes
+// deeper trees are refused instead of overflowing the stack of the importing thread
+const MAX_NESTING: usize = 256;
+
+// why an entry has no contents in an ncdu export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Exclusion {
+    Pattern, // left out on purpose
+    OtherFs, // mount point (or pseudo file system) that wasn't descended into
+}
+
+// an entry as written by another tool, before the scan options are applied
+#[derive(Debug, Default)]
+struct Entry {
+    name: String,
+    kind: NodeKind,
+    size: u64, // apparent size (0 from du), of the directory itself for directories
+    allocated_size: u64,
+    mtime: Option<SystemTime>,
+    hard_link: Option<(u64, u64)>, // (device, inode) of files with several links
+    mount_point: bool,
+    read_error: bool,
+    excluded: Option<Exclusion>,
+    children: Option<Vec<Entry>>, // `Some` for directories
+}
+
+// read an ncdu export (`ncdu -o`) or the output of `du -ak` taken on another machine
+pub fn import_file(path: &Path, options: &ScanOptions) -> io::Result<Snapshot> {
+    let data = fs::read(path)?;
+    // du doesn't say when it ran
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or_else(|_| SystemTime::now());
+    import_data(&data, modified, options)
+}
+
+// like `import_file` for data already read, `modified` stands in for the time du ran
+pub fn import_data(
+    data: &[u8],
+    modified: SystemTime,
+    options: &ScanOptions,
+) -> io::Result<Snapshot> {
+    let mut options = options.clone();
+    let mut bytes = options.size_mode.bytes();
+    let (root, scanned_at) = match data.iter().find(|byte| !byte.is_ascii_whitespace()) {
+        Some(b'[') => parse_ncdu(data)?,
+        Some(_) => {
+            // du doesn't say how big the files are, only the disk usage can be pruned and shown
+            bytes = SizeMode::Allocated;
+            if options.size_mode == SizeMode::Apparent {
+                options.size_mode = SizeMode::Allocated;
+            }
+            (parse_du(data)?, modified)
+        }
+        None => return Err(invalid("nothing to import")),
+    };
+
+    let result = build_result(root, &options, bytes);
+    Ok(Snapshot {
+        scanned_at,
+        options,
+        result,
+    })
+}
+
+// `[major, minor, metadata, root]`, directories are arrays of their info followed by their entries
+fn parse_ncdu(data: &[u8]) -> io::Result<(Entry, SystemTime)> {
+    let mut parser = JsonParser { data, pos: 0 };
+    let value = parser.value(0)?;
+    if parser.peek().is_some() {
+        return Err(parser.error("trailing data"));
+    }
+
+    let Json::Array(items) = value else {
+        return Err(invalid("not an ncdu export"));
+    };
+    let mut items = items.into_iter();
+    if items.next().and_then(|major| major.as_u64()) != Some(1) {
+        return Err(invalid("unsupported ncdu export version"));
+    }
+    items.next(); // minor versions only add fields
+
+    let scanned_at = items
+        .next()
+        .and_then(|metadata| metadata.get("timestamp").and_then(Json::as_u64))
+        .map_or_else(SystemTime::now, |seconds| {
+            UNIX_EPOCH + Duration::from_secs(seconds)
+        });
+    let root = items
+        .next()
+        .ok_or_else(|| invalid("ncdu export without a root directory"))?;
+    let mut root = ncdu_entry(root, 0)?;
+    root.mount_point = false;
+    if root.children.is_none() {
+        return Err(invalid("ncdu export whose root isn't a directory"));
+    }
+
+    Ok((root, scanned_at))
+}
+
+fn ncdu_entry(value: Json, parent_device: u64) -> io::Result<Entry> {
+    match value {
+        Json::Object(fields) => Ok(ncdu_info(fields, parent_device, false).0),
+        Json::Array(items) => {
+            let mut items = items.into_iter();
+            let Some(Json::Object(fields)) = items.next() else {
+                return Err(invalid("ncdu directory without its info"));
+            };
+
+            let (mut entry, device) = ncdu_info(fields, parent_device, true);
+            entry.children = Some(
+                items
+                    .map(|item| ncdu_entry(item, device))
+                    .collect::<io::Result<_>>()?,
+            );
+            Ok(entry)
+        }
+        _ => Err(invalid("unexpected value in ncdu export")),
+    }
+}
+
+// the entry and the device it lives on (ncdu only writes it when it changes)
+fn ncdu_info(fields: Vec<(String, Json)>, parent_device: u64, is_dir: bool) -> (Entry, u64) {
+    let mut entry = Entry {
+        kind: if is_dir {
+            NodeKind::Directory
+        } else {
+            NodeKind::File
+        },
+        ..Entry::default()
+    };
+    let mut device = parent_device;
+    let mut inode = None;
+    let mut linked = false;
+
+    for (key, value) in fields {
+        match key.as_str() {
+            "name" => {
+                if let Json::String(name) = value {
+                    entry.name = name;
+                }
+            }
+            "asize" => entry.size = value.as_u64().unwrap_or(0),
+            "dsize" => entry.allocated_size = value.as_u64().unwrap_or(0),
+            "dev" => device = value.as_u64().unwrap_or(parent_device),
+            "ino" => inode = value.as_u64(),
+            "hlnkc" => linked |= value.is_true(),
+            "nlink" => linked |= value.as_u64().is_some_and(|links| links > 1),
+            "mtime" => {
+                entry.mtime = value
+                    .as_u64()
+                    .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds))
+            }
+            "read_error" => entry.read_error = value.is_true(),
+            "notreg" if !is_dir && value.is_true() => entry.kind = NodeKind::Other,
+            "excluded" => {
+                entry.excluded = match value {
+                    Json::String(reason) if reason == "pattern" || reason == "frmlnk" => {
+                        Some(Exclusion::Pattern)
+                    }
+                    _ => Some(Exclusion::OtherFs),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if linked && !is_dir {
+        entry.hard_link = inode.map(|inode| (device, inode));
+    }
+    entry.mount_point = is_dir && device != parent_device;
+
+    (entry, device)
+}
+
+// `SIZE<tab>PATH` lines, every directory comes after its contents and the root comes last
+fn parse_du(data: &[u8]) -> io::Result<Entry> {
+    let text = String::from_utf8_lossy(data);
+
+    let mut lines = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (size, path) = line
+            .split_once('\t')
+            .ok_or_else(|| invalid(&format!("line {}: expected 'SIZE<tab>PATH'", number + 1)))?;
+        let size: u64 = size
+            .trim()
+            .parse()
+            .map_err(|_| invalid(&format!("line {}: invalid size '{}'", number + 1, size)))?;
+        let size = size
+            .checked_mul(DU_BLOCK_SIZE)
+            .ok_or_else(|| invalid(&format!("line {}: size too large", number + 1)))?;
+        lines.push((PathBuf::from(path), size));
+    }
+
+    let root = lines
+        .len()
+        .checked_sub(1)
+        .ok_or_else(|| invalid("no du output"))?;
+    let root_path = lines[root].0.clone();
+    let root_depth = root_path.components().count();
+
+    let mut children: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+    for (index, (path, _)) in lines.iter().enumerate() {
+        if *path != root_path
+            && path.starts_with(&root_path)
+            && let Some(parent) = path.parent()
+        {
+            if path.components().count() - root_depth > MAX_NESTING {
+                return Err(invalid("du output is nested too deeply"));
+            }
+            children
+                .entry(parent.to_path_buf())
+                .or_default()
+                .push(index);
+        }
+    }
+
+    let mut root = du_entry(&lines, root, &mut children);
+    root.kind = NodeKind::Directory;
+    root.children.get_or_insert_with(Vec::new);
+    root.name = root_path.to_string_lossy().to_string();
+    Ok(root)
+}
+
+fn du_entry(
+    lines: &[(PathBuf, u64)],
+    index: usize,
+    children: &mut HashMap<PathBuf, Vec<usize>>,
+) -> Entry {
+    let (path, size) = &lines[index];
+    // du only reports disk usage, the apparent size stays unknown (0)
+    let mut entry = Entry {
+        name: path
+            .file_name()
+            .map_or_else(|| path.to_string_lossy(), |name| name.to_string_lossy())
+            .to_string(),
+        kind: NodeKind::Other,
+        allocated_size: *size,
+        ..Entry::default()
+    };
+
+    // du can't tell an empty directory from a file, entries without contents are
+    // neither counted as files nor as folders
+    if let Some(indices) = children.remove(path) {
+        // du sizes include everything below, the directory keeps what is left for itself
+        let own_size = indices.iter().fold(*size, |own_size, &child| {
+            own_size.saturating_sub(lines[child].1)
+        });
+
+        entry.kind = NodeKind::Directory;
+        entry.allocated_size = own_size;
+        entry.children = Some(
+            indices
+                .into_iter()
+                .map(|child| du_entry(lines, child, children))
+                .collect(),
+        );
+    }
+
+    entry
+}
+
+// what `build_tree` does for a scan, for entries read from another tool
+struct TreeBuilder<'a> {
+    options: &'a ScanOptions,
+    mode: SizeMode, // thresholds are given in bytes
+    hard_links: HashSet<(u64, u64)>,
+    largest: LargestFiles,
+    errors: Vec<ScanError>,
+    skipped_mounts: Vec<PathBuf>,
+    excluded: Vec<PathBuf>,
+    empty_dirs: Vec<PathBuf>,
+    empty_files: Vec<PathBuf>,
+}
+
+// `bytes` is the size the byte thresholds apply to
+fn build_result(root: Entry, options: &ScanOptions, bytes: SizeMode) -> ScanResult {
+    let mut builder = TreeBuilder {
+        options,
+        mode: bytes,
+        hard_links: HashSet::new(),
+        largest: LargestFiles::new(options.largest_files),
+        errors: Vec::new(),
+        skipped_mounts: Vec::new(),
+        excluded: Vec::new(),
+        empty_dirs: Vec::new(),
+        empty_files: Vec::new(),
+    };
+
+    // the root is named by its full path in both formats
+    let path = PathBuf::from(&root.name);
+    let (mut root_node, _) = builder.directory(root, path, 0);
+    if let Some(name) = root_node.path.file_name() {
+        root_node.name = name.to_string_lossy().to_string();
+    }
+
+    let threshold = (root_node.size_in(options.size_mode) as f64 * options.min_fraction) as u64;
+    filter_hierarchy(&mut root_node, threshold, options.size_mode);
+    root_node.cache_dominant();
+    root_node.sort_children();
+
+    ScanResult {
+        root: root_node,
+        errors: builder.errors,
+        skipped_mounts: builder.skipped_mounts,
+        excluded: builder.excluded,
+        largest_files: builder.largest.into_sorted(),
+        empty_dirs: builder.empty_dirs,
+        empty_files: builder.empty_files,
+    }
+}
+
+impl TreeBuilder<'_> {
+    // the node and whether it only holds empty directories
+    fn directory(&mut self, entry: Entry, path: PathBuf, depth: usize) -> (FolderNode, bool) {
+        let mut node = FolderNode::new(entry.name, path, entry.size, entry.allocated_size);
+        node.incomplete = entry.read_error;
+        node.mount_point = entry.mount_point;
+        if entry.read_error {
+            self.errors.push(ScanError {
+                path: node.path.clone(),
+                kind: ScanErrorKind::Io,
+            });
+        }
+        if entry.excluded == Some(Exclusion::OtherFs) {
+            node.mount_point = true;
+            node.skipped = true;
+            self.skipped_mounts.push(node.path.clone());
+        }
+
+        let mut only_dirs = !node.incomplete && !node.skipped;
+        let mut empty_children = Vec::new();
+        for child in entry.children.unwrap_or_default() {
+            let child_path = node.path.join(&child.name);
+            if child.excluded == Some(Exclusion::Pattern) {
+                self.excluded.push(child_path);
+                only_dirs = false;
+                continue;
+            }
+            if child.children.is_none() {
+                self.file(&mut node, child, child_path);
+                only_dirs = false;
+                continue;
+            }
+
+            let (child_node, empty) = self.directory(child, child_path, depth + 1);
+            if empty {
+                empty_children.push(child_node.path.clone());
+            } else {
+                only_dirs = false;
+            }
+
+            node.size += child_node.size;
+            node.allocated_size += child_node.allocated_size;
+            node.hard_link_size += child_node.hard_link_size;
+            node.file_count += child_node.file_count;
+            node.dir_count += child_node.dir_count + 1;
+            node.other_count += child_node.other_count;
+            node.times.merge(&child_node.times);
+            node.types.merge(&child_node.types);
+            node.owners.merge(&child_node.owners);
+            node.incomplete |= child_node.incomplete;
+            node.add_child(child_node);
+        }
+
+        // only the top-most empty directory below the root is reported
+        if !only_dirs || depth == 0 {
+            self.empty_dirs.append(&mut empty_children);
+        }
+
+        // don't keep the structure of small or too deep folders
+        if reached_max_depth(self.options, depth) {
+            node.merge_children(|_| true);
+        } else if depth > 0 && node.size_in(self.mode) <= self.options.detail_threshold {
+            node.merge_children(|child| !child.mount_point);
+        }
+
+        (node, only_dirs)
+    }
+
+    // the size always goes to `parent`, big files also get a leaf node
+    fn file(&mut self, parent: &mut FolderNode, entry: Entry, path: PathBuf) {
+        // further links to a file take no space of their own
+        if let Some(id) = entry.hard_link
+            && !self.hard_links.insert(id)
+        {
+            return;
+        }
+
+        let mut leaf = FolderNode::new(entry.name, path, entry.size, entry.allocated_size);
+        leaf.kind = entry.kind;
+        if let Some(mtime) = entry.mtime {
+            leaf.times.add_mtime(mtime);
+        }
+        if entry.hard_link.is_some() {
+            leaf.hard_link_size = leaf.size;
+        }
+        match entry.kind {
+            NodeKind::File => {
+                leaf.file_count = 1;
+                leaf.types.add(&leaf.path, leaf.size, leaf.allocated_size);
+                if leaf.size == 0 {
+                    self.empty_files.push(leaf.path.clone());
+                }
+
+                let file = LargeFile {
+                    path: leaf.path.clone(),
+                    size: leaf.size,
+                    allocated_size: leaf.allocated_size,
+                };
+                self.largest.offer(file.size_in(self.mode), file);
+            }
+            _ => leaf.other_count = 1,
+        }
+
+        parent.size += leaf.size;
+        parent.allocated_size += leaf.allocated_size;
+        parent.hard_link_size += leaf.hard_link_size;
+        parent.file_count += leaf.file_count;
+        parent.other_count += leaf.other_count;
+        parent.times.merge(&leaf.times);
+        parent.types.merge(&leaf.types);
+
+        if leaf.size_in(self.mode) >= self.options.file_threshold {
+            parent.add_child(leaf);
+        }
+    }
+}
+
+// just enough json for ncdu exports
+#[derive(Debug)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String), // kept as text, sizes and inodes don't always fit an f64
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    #[inline]
+    fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::Number(number) => number.parse().ok(),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn is_true(&self) -> bool {
+        matches!(self, Self::Bool(true))
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Self::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    // next byte after any whitespace
+    fn peek(&mut self) -> Option<u8> {
+        while self
+            .data
+            .get(self.pos)
+            .is_some_and(|byte| byte.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+        self.data.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> io::Result<()> {
+        if self.peek() != Some(byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    #[inline]
+    fn error(&self, message: &str) -> io::Error {
+        invalid(&format!("{} at byte {}", message, self.pos))
+    }
+
+    fn value(&mut self, depth: usize) -> io::Result<Json> {
+        if depth > MAX_NESTING {
+            return Err(self.error("nested too deeply"));
+        }
+
+        match self.peek() {
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Json::Array(items));
+                        }
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    fields.push((key, self.value(depth + 1)?));
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Json::Object(fields));
+                        }
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            Some(b'"') => self.string().map(Json::String),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                while self.data.get(self.pos).is_some_and(|byte| {
+                    matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+                }) {
+                    self.pos += 1;
+                }
+                let number = String::from_utf8_lossy(&self.data[start..self.pos]);
+                Ok(Json::Number(number.to_string()))
+            }
+            Some(_) => Err(self.error("unexpected input")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn literal(&mut self, text: &str, value: Json) -> io::Result<Json> {
+        if !self.data[self.pos..].starts_with(text.as_bytes()) {
+            return Err(self.error("unexpected input"));
+        }
+        self.pos += text.len();
+        Ok(value)
+    }
+
+    // ncdu writes names that aren't valid utf-8 as raw bytes
+    fn string(&mut self) -> io::Result<String> {
+        self.expect(b'"')?;
+
+        let mut bytes = Vec::new();
+        loop {
+            let Some(&byte) = self.data.get(self.pos) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+
+            match byte {
+                b'"' => return Ok(String::from_utf8_lossy(&bytes).to_string()),
+                b'\\' => {
+                    let Some(&escape) = self.data.get(self.pos) else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.pos += 1;
+
+                    match escape {
+                        b'"' | b'\\' | b'/' => bytes.push(escape),
+                        b'b' => bytes.push(0x08),
+                        b'f' => bytes.push(0x0c),
+                        b'n' => bytes.push(b'\n'),
+                        b'r' => bytes.push(b'\r'),
+                        b't' => bytes.push(b'\t'),
+                        b'u' => {
+                            let c = self.unicode_escape()?;
+                            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                _ => bytes.push(byte),
+            }
+        }
+    }
+
+    // the four hex digits after `\u`, and the low half of a surrogate pair
+    fn unicode_escape(&mut self) -> io::Result<char> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) || !self.data[self.pos..].starts_with(b"\\u") {
+            return Ok(char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER));
+        }
+
+        self.pos += 2;
+        let low = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            // a lone high half, the next escape is read on its own
+            self.pos -= 6;
+            return Ok(char::REPLACEMENT_CHARACTER);
+        }
+        let code = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+        Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn hex4(&mut self) -> io::Result<u32> {
+        let code = self
+            .data
+            .get(self.pos..self.pos + 4)
+            .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+}
+
+#[inline]
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(text: &str) -> io::Result<Json> {
+        let mut parser = JsonParser {
+            data: text.as_bytes(),
+            pos: 0,
+        };
+        parser.value(0)
+    }
+
+    fn string(text: &str) -> String {
+        match json(text).unwrap() {
+            Json::String(text) => text,
+            other => panic!("not a string: {:?}", other),
+        }
+    }
+
+    // everything kept, so the tree can be checked node by node
+    fn detailed() -> ScanOptions {
+        ScanOptions {
+            detail_threshold: 0,
+            min_fraction: 0.0,
+            file_threshold: 0,
+            ..ScanOptions::default()
+        }
+    }
+
+    #[test]
+    fn json_escapes_and_surrogates() {
+        assert_eq!(string(r#""a\"b\\c\/d\n\t""#), "a\"b\\c/d\n\t");
+        assert_eq!(string(r#""\u00e9\u20ac""#), "é€");
+        assert_eq!(string(r#""\ud83d\ude00""#), "😀");
+        // a lone high half doesn't swallow the escape after it
+        assert_eq!(string(r#""\ud83d\u0041""#), "\u{fffd}A");
+        assert_eq!(string(r#""\ude00""#), "\u{fffd}");
+        assert!(json(r#""\u+041""#).is_err());
+        assert!(json(r#""\x""#).is_err());
+    }
+
+    #[test]
+    fn malformed_json_is_rejected() {
+        for text in [
+            "",
+            "[1,",
+            "[1 2]",
+            "{\"a\"}",
+            "{\"a\":1,}",
+            "\"open",
+            "tru",
+            "@",
+        ] {
+            assert!(json(text).is_err(), "{:?}", text);
+        }
+        assert!(parse_ncdu(b"[1,0,{}]").is_err());
+        assert!(parse_ncdu(b"[2,0,{},[{\"name\":\"/\"}]]").is_err());
+        assert!(parse_ncdu(b"[1,0,{},{\"name\":\"/\"}]").is_err());
+        assert!(parse_ncdu(b"[1,0,{},[{\"name\":\"/\"}]] x").is_err());
+
+        let deep = "[".repeat(MAX_NESTING + 2) + &"]".repeat(MAX_NESTING + 2);
+        assert!(json(&deep).is_err());
+    }
+
+    #[test]
+    fn ncdu_export_is_read() {
+        let data = br#"[1,2,{"progname":"ncdu","timestamp":1700000000},
+            [{"name":"/data","asize":4096,"dsize":4096,"dev":1},
+             {"name":"a.mkv","asize":5000,"dsize":8192},
+             {"name":"link1","asize":100,"dsize":4096,"ino":7,"hlnkc":true},
+             {"name":"link2","asize":100,"dsize":4096,"ino":7,"hlnkc":true},
+             {"name":"fifo","notreg":true},
+             {"name":"node_modules","excluded":"pattern"},
+             [{"name":"empty","asize":4096,"dsize":4096}],
+             [{"name":"proc","dev":2,"excluded":"othfs"}],
+             [{"name":"bad","read_error":true}]]]"#;
+        let (root, scanned_at) = parse_ncdu(data).unwrap();
+        assert_eq!(scanned_at, UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+
+        let options = detailed();
+        let result = build_result(root, &options, options.size_mode.bytes());
+        let root = &result.root;
+        assert_eq!(root.path, PathBuf::from("/data"));
+        assert_eq!(root.name, "data");
+        // the second link to the same inode takes no space
+        assert_eq!(root.size, 4096 + 5000 + 100 + 4096);
+        assert_eq!(root.allocated_size, 4096 + 8192 + 4096 + 4096);
+        assert_eq!(root.file_count, 2);
+        assert_eq!(root.other_count, 1);
+        assert_eq!(root.dir_count, 3);
+
+        assert_eq!(result.excluded, vec![PathBuf::from("/data/node_modules")]);
+        assert_eq!(result.skipped_mounts, vec![PathBuf::from("/data/proc")]);
+        assert_eq!(result.empty_dirs, vec![PathBuf::from("/data/empty")]);
+        assert_eq!(result.errors.len(), 1);
+        assert!(root.incomplete);
+    }
+
+    #[test]
+    fn du_output_is_read() {
+        let data = b"8\t/srv/a/big.iso\n0\t/srv/a/empty.txt\n4\t/srv/a/sub\n16\t/srv/a\n20\t/srv\n";
+        // as `import_file` does, there are no apparent sizes to prune by
+        let options = ScanOptions {
+            size_mode: SizeMode::Allocated,
+            ..detailed()
+        };
+        let result = build_result(parse_du(data).unwrap(), &options, SizeMode::Allocated);
+        let root = &result.root;
+
+        assert_eq!(root.path, PathBuf::from("/srv"));
+        assert_eq!(root.allocated_size, 20 * 1024);
+        assert_eq!(root.size, 0);
+        // leaves might be files or empty folders
+        assert_eq!(root.file_count, 0);
+        assert_eq!(root.other_count, 3);
+        assert_eq!(root.dir_count, 1);
+        assert!(result.empty_files.is_empty());
+        assert!(result.largest_files.is_empty());
+
+        let a = &root.children[0];
+        assert_eq!(a.kind, NodeKind::Directory);
+        assert_eq!(a.allocated_size, 16 * 1024);
+        assert!(a.children.iter().all(|child| child.kind == NodeKind::Other));
+    }
+
+    #[test]
+    fn du_output_is_pruned_by_disk_usage_in_every_mode() {
+        let data = b"4096\t/srv/a/big.iso\n8192\t/srv/a/sub/big2\n8196\t/srv/a/sub\n12296\t/srv/a\n12300\t/srv\n";
+
+        for mode in SizeMode::ALL {
+            let options = ScanOptions {
+                size_mode: mode,
+                ..ScanOptions::default()
+            };
+            let snapshot = import_data(data, UNIX_EPOCH, &options).unwrap();
+            // there is nothing to show in the apparent size view
+            let expected = match mode {
+                SizeMode::Apparent => SizeMode::Allocated,
+                mode => mode,
+            };
+            assert_eq!(snapshot.options.size_mode, expected);
+
+            let a = &snapshot.result.root.children[0];
+            let mut names: Vec<&str> = a.children.iter().map(|child| child.name.as_str()).collect();
+            names.sort();
+            assert_eq!(names, ["big.iso", "sub"], "{:?}", mode);
+            let sub = a.children.iter().find(|child| child.name == "sub").unwrap();
+            assert_eq!(sub.children[0].name, "big2", "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn malformed_du_output_is_rejected() {
+        assert!(parse_du(b"").is_err());
+        assert!(parse_du(b"12 /srv\n").is_err());
+        assert!(parse_du(b"x\t/srv\n").is_err());
+        assert!(parse_du(b"99999999999999999999\t/srv\n").is_err());
+
+        let deep = format!("4\t/r{}\n4\t/r\n", "/d".repeat(MAX_NESTING + 1));
+        assert!(parse_du(deep.as_bytes()).is_err());
+    }
+}
@@ -1,32 +1,148 @@
+mod cli;
+mod config;
+mod duplicates;
+mod export;
+mod filter;
 mod folder;
+mod ignore;
+mod import;
+mod owners;
+mod pool;
 mod scan;
+mod snapshot;
 mod theme;
+mod types;
 mod ui;
 mod utils;
 mod widgets;
 
 use fltk::{enums, prelude::*, *};
+use folder::{FolderNode, SizeMode};
+use ignore::IgnoreMode;
 use rfd::FileDialog;
 use std::cell::RefCell;
-use std::env;
-use std::path::PathBuf;
-use std::rc::Rc;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::{Rc, Weak};
+use std::thread;
+use std::time::SystemTime;
 
-use scan::scan_folder_hierarchy;
+use duplicates::{DuplicateGroup, find_duplicates};
+use export::{ExportFormat, ExportTarget, export_to_file};
+use import::import_file;
+use scan::{
+    CancelToken, LargeFile, MaxDepth, MinFraction, ScanCancelled, ScanError, ScanOptions,
+    ScanProgress, ScanResult, SymlinkPolicy, ThreadCount, scan_folder_hierarchy,
+};
+use snapshot::Snapshot;
 use theme::*;
-use ui::{style_button, update_progress_bar};
-use widgets::TreemapWidget;
+use ui::{
+    fill_largest_files, format_progress, show_duplicates, show_empty_report, show_filter_dialog,
+    show_owner_breakdown, show_scan_settings, show_scan_summary, show_type_breakdown,
+    style_browser, style_button, style_choice, style_menu_button, update_progress_bar,
+};
+use utils::{format_date, format_size};
+use widgets::{ColorMode, TreemapWidget};
+
+// messages sent from the scan thread back to the UI thread
+enum Message {
+    Progress(ScanProgress),
+    Finished(Snapshot),
+    Opened(Snapshot), // loaded from a snapshot file instead of scanned
+    Duplicates(Vec<DuplicateGroup>),
+    Failed(String),
+    Cancelled,
+}
+
+// where the tree for `--export` comes from
+enum Source<'a> {
+    Folder(&'a Path),
+    Snapshot(&'a Path),
+    Import(&'a Path), // ncdu export or du output
+}
+
+// view menu entries of the size modes
+const SIZE_MODE_ITEMS: [(&str, SizeMode); 3] = [
+    ("View/Apparent size", SizeMode::Apparent),
+    ("View/Disk usage", SizeMode::Allocated),
+    ("View/Item count", SizeMode::Items),
+];
 
 struct AppState {
     selected_path: PathBuf,
     progress: frame::Frame,
     status_text: frame::Frame,
     treemap: TreemapWidget,
+    files_list: browser::HoldBrowser,
+    files_sort: menu::Choice,
+    menu_button: menu::MenuButton,
+    largest_files: Vec<LargeFile>, // in the order shown by `files_list`
     scan_button: button::Button,
+    cancel_button: button::Button,
+    cancel_token: Option<CancelToken>,
+    errors_button: button::Button,
+    scan_errors: Vec<ScanError>,
+    excluded_paths: Vec<PathBuf>,
+    skipped_mounts: Vec<PathBuf>,
+    scanned: Option<(SystemTime, ScanOptions)>, // when and how the shown tree was made
+    empty_dirs: Vec<PathBuf>,
+    empty_files: Vec<PathBuf>,
+    live: bool, // the shown tree was scanned here, not opened or imported
+    scan_options: ScanOptions,
+    sender: app::Sender<Message>,
 }
 
 fn main() {
+    let args = cli::parse_args();
+
+    let mut scan_options = ScanOptions::default();
+    config::load_into(&mut scan_options, args.config.as_deref());
+
+    if let Some(ThreadCount(threads)) = args.threads {
+        scan_options.threads = threads;
+    }
+    if let Some(detail_threshold) = args.detail_threshold {
+        scan_options.detail_threshold = detail_threshold;
+    }
+    if let Some(MinFraction(min_fraction)) = args.min_fraction {
+        scan_options.min_fraction = min_fraction;
+    }
+    if let Some(MaxDepth(max_depth)) = args.max_depth {
+        scan_options.max_depth = max_depth;
+    }
+    if let Some(file_threshold) = args.file_threshold {
+        scan_options.file_threshold = file_threshold;
+    }
+    if let Some(largest_files) = args.largest_files {
+        scan_options.largest_files = largest_files;
+    }
+    if let Some(size_mode) = args.size_mode {
+        scan_options.size_mode = size_mode;
+    }
+    scan_options.one_file_system |= args.one_file_system;
+    if let Some(symlinks) = args.symlinks {
+        scan_options.symlinks = symlinks;
+    }
+    scan_options.exclude.extend(args.exclude);
+    scan_options.include.extend(args.include);
+    if let Some(ignore_mode) = args.ignore_mode {
+        scan_options.ignore_mode = ignore_mode;
+    }
+
+    // exporting doesn't need a window
+    if let Some(target) = &args.export {
+        let source = if let Some(snapshot) = &args.snapshot {
+            Some(Source::Snapshot(snapshot))
+        } else if let Some(import) = &args.import {
+            Some(Source::Import(import))
+        } else {
+            args.path.as_deref().map(Source::Folder)
+        };
+        process::exit(run_export(target, source, &scan_options));
+    }
+
     let app = app::App::default();
+    let (sender, receiver) = app::channel::<Message>();
 
     app::set_background_color(
         CATPPUCCIN_BASE as u8,
@@ -34,7 +150,7 @@ fn main() {
         (CATPPUCCIN_BASE >> 16) as u8,
     );
 
-    let mut wind = window::Window::new(100, 100, 900, 900, "folder-scan");
+    let mut wind = window::Window::new(100, 100, 1200, 900, "folder-scan");
     wind.set_color(enums::Color::from_u32(CATPPUCCIN_BASE));
     wind.make_resizable(true);
     wind.size_range(675, 400, 0, 0);
@@ -45,7 +161,23 @@ fn main() {
         progress: frame::Frame::new(20, 80, 860, 30, ""),
         status_text: frame::Frame::new(20, 50, 860, 30, "Select a folder..."),
         treemap: TreemapWidget::new(20, 180, 860, 700),
+        files_list: browser::HoldBrowser::new(900, 180, 280, 700, ""),
+        files_sort: menu::Choice::new(1000, 130, 180, 30, "Largest files"),
+        largest_files: Vec::new(),
+        menu_button: menu::MenuButton::new(20, 125, 140, 40, "Menu"),
         scan_button: button::Button::new(475, 125, 140, 40, "Scan"),
+        cancel_button: button::Button::new(475, 125, 140, 40, "Cancel"),
+        cancel_token: None,
+        errors_button: button::Button::new(740, 125, 140, 40, ""),
+        scan_errors: Vec::new(),
+        excluded_paths: Vec::new(),
+        skipped_mounts: Vec::new(),
+        scanned: None,
+        empty_dirs: Vec::new(),
+        empty_files: Vec::new(),
+        live: false,
+        scan_options,
+        sender,
     }));
 
     // progress bar styling
@@ -76,6 +208,36 @@ fn main() {
         .set_label_color(enums::Color::from_u32(CATPPUCCIN_BASE));
     app_state.borrow_mut().scan_button.deactivate();
 
+    // cancel button styling (only visible while scanning)
+    style_button(&mut app_state.borrow_mut().cancel_button, CATPPUCCIN_RED);
+    app_state
+        .borrow_mut()
+        .cancel_button
+        .set_color(enums::Color::from_u32(CATPPUCCIN_RED));
+    app_state
+        .borrow_mut()
+        .cancel_button
+        .set_label_color(enums::Color::from_u32(CATPPUCCIN_BASE));
+    app_state.borrow_mut().cancel_button.hide();
+
+    // errors button styling (only visible after a scan with errors or exclusions)
+    style_button(&mut app_state.borrow_mut().errors_button, CATPPUCCIN_RED);
+    app_state
+        .borrow_mut()
+        .errors_button
+        .set_label_color(enums::Color::from_u32(CATPPUCCIN_RED));
+    app_state.borrow_mut().errors_button.hide();
+
+    // largest files list styling
+    style_browser(&mut app_state.borrow_mut().files_list);
+    app_state.borrow_mut().files_list.set_column_widths(&[80]);
+    style_choice(&mut app_state.borrow_mut().files_sort);
+    app_state
+        .borrow_mut()
+        .files_sort
+        .add_choice("Size|Name|Path");
+    app_state.borrow_mut().files_sort.set_value(0);
+
     // title text
     let mut title = frame::Frame::new(20, 10, 860, 30, "Folder Scan");
     title.set_label_size(24);
@@ -86,13 +248,293 @@ fn main() {
     let mut folder_select_button = button::Button::new(285, 125, 140, 40, "Choose Folder");
     style_button(&mut folder_select_button, CATPPUCCIN_LAVENDER);
 
+    // menu with view settings
+    let mut menu_button = app_state.borrow().menu_button.clone();
+    style_menu_button(&mut menu_button);
+
+    {
+        let state_weak = Rc::downgrade(&app_state);
+        menu_button.add(
+            "File/Open snapshot...",
+            enums::Shortcut::None,
+            menu::MenuFlag::Normal,
+            move |_| {
+                if let Some(state) = state_weak.upgrade() {
+                    handle_open_snapshot(&mut state.borrow_mut(), None);
+                }
+            },
+        );
+    }
+
+    {
+        let state_weak = Rc::downgrade(&app_state);
+        menu_button.add(
+            "File/Import ncdu or du output...",
+            enums::Shortcut::None,
+            menu::MenuFlag::Normal,
+            move |_| {
+                if let Some(state) = state_weak.upgrade() {
+                    handle_import(&mut state.borrow_mut(), None);
+                }
+            },
+        );
+    }
+
+    {
+        let state_weak = Rc::downgrade(&app_state);
+        menu_button.add(
+            "File/Save snapshot...",
+            enums::Shortcut::None,
+            menu::MenuFlag::Normal,
+            move |_| {
+                if let Some(state) = state_weak.upgrade() {
+                    handle_save_snapshot(&mut state.borrow_mut());
+                }
+            },
+        );
+    }
+
+    for (label, format) in [
+        ("File/Export/JSON...", ExportFormat::Json),
+        ("File/Export/CSV...", ExportFormat::Csv),
+        ("File/Export/ncdu...", ExportFormat::Ncdu),
+    ] {
+        let state_weak = Rc::downgrade(&app_state);
+        menu_button.add(
+            label,
+            enums::Shortcut::None,
+            menu::MenuFlag::Normal,
+            move |_| {
+                if let Some(state) = state_weak.upgrade() {
+                    handle_export(&mut state.borrow_mut(), format);
+                }
+            },
+        );
+    }
+
+    let size_mode = app_state.borrow().scan_options.size_mode;
+    app_state.borrow_mut().treemap.set_size_mode(size_mode);
+
+    for (label, mode) in SIZE_MODE_ITEMS {
+        let state_weak = Rc::downgrade(&app_state);
+        menu_button.add(
+            label,
+            enums::Shortcut::None,
+            menu::MenuFlag::Radio,
+            move |_| {
+                if let Some(state) = state_weak.upgrade() {
+                    handle_size_mode(&mut state.borrow_mut(), mode);
+                }
+            },
+        );
+
+        if mode == size_mode
+            && let Some(mut item) = menu_button.find_item(label)
+        {
+            item.set();
+        }
+    }
+
+    {
+        let label = "Scan/Stay on one file system";
+        let state_weak = Rc::downgrade(&app_state);
+        menu_button.add(
+            label,
+            enums::Shortcut::None,
+            menu::MenuFlag::Toggle,
+            move |m| {
+                if let Some(state) = state_weak.upgrade()
+                    && let Some(item) = m.mvalue()
+                {
+                    state.borrow_mut().scan_options.one_file_system = item.value();
+                }
+            },
+        );
+
+        if app_state.borrow().scan_options.one_file_system
+            && let Some(mut item) = menu_button.find_item(label)
+        {
+            item.set();
+        }
+    }
+
+    for (label, mode) in [
+        ("Color/By depth", ColorMode::Depth),
+        ("Color/By age", ColorMode::Age),
+        ("Color/By file type", ColorMode::FileType),
+        ("Color/By owner", ColorMode::Owner),
+    ] {
+        let state_weak = Rc::downgrade(&app_state);
+        menu_button.add(
+            label,
+            enums::Shortcut::None,
+            menu::MenuFlag::Radio,
+            move |_| {
+                if let Some(state) = state_weak.upgrade() {
+                    state.borrow_mut().treemap.set_color_mode(mode);
+                }
+            },
+        );
+
+        if mode == ColorMode::default()
+            && let Some(mut item) = menu_button.find_item(label)
+        {
+            item.set();
+        }
+    }
+
+    {
+        let state_weak = Rc::downgrade(&app_state);
+        menu_button.add(
+            "View/File types...",
+            enums::Shortcut::None,
+            menu::MenuFlag::Normal,
+            move |_| {
+                if let Some(state) = state_weak.upgrade() {
+                    state.borrow().treemap.with_root(|root, mode| {
+                        show_type_breakdown(&root.name, &root.types, mode);
+                    });
+                }
+            },
+        );
+    }
+
+    {
+        let state_weak = Rc::downgrade(&app_state);
+        menu_button.add(
+            "View/Owners...",
+            enums::Shortcut::None,
+            menu::MenuFlag::Normal,
+            move |_| {
+                if let Some(state) = state_weak.upgrade() {
+                    state.borrow().treemap.with_root(|root, mode| {
+                        show_owner_breakdown(&root.name, &root.owners, mode);
+                    });
+                }
+            },
+        );
+    }
+
+    {
+        let state_weak = Rc::downgrade(&app_state);
+        menu_button.add(
+            "View/Empty folders and files...",
+            enums::Shortcut::None,
+            menu::MenuFlag::Normal,
+            move |_| {
+                if let Some(state) = state_weak.upgrade() {
+                    let state = state.borrow();
+                    show_empty_report(&state.empty_dirs, &state.empty_files, state.live);
+                }
+            },
+        );
+    }
+
+    let symlinks = app_state.borrow().scan_options.symlinks;
+    for (label, policy) in [
+        ("Scan/Symlinks/Never follow", SymlinkPolicy::Never),
+        (
+            "Scan/Symlinks/Follow within folder",
+            SymlinkPolicy::WithinRoot,
+        ),
+        ("Scan/Symlinks/Always follow", SymlinkPolicy::Always),
+    ] {
+        let state_weak = Rc::downgrade(&app_state);
+        menu_button.add(
+            label,
+            enums::Shortcut::None,
+            menu::MenuFlag::Radio,
+            move |_| {
+                if let Some(state) = state_weak.upgrade() {
+                    state.borrow_mut().scan_options.symlinks = policy;
+                }
+            },
+        );
+
+        if policy == symlinks
+            && let Some(mut item) = menu_button.find_item(label)
+        {
+            item.set();
+        }
+    }
+
+    let ignore_mode = app_state.borrow().scan_options.ignore_mode;
+    for (label, mode) in [
+        ("Scan/Git ignore rules/Show everything", IgnoreMode::Off),
+        ("Scan/Git ignore rules/Tracked only", IgnoreMode::NotIgnored),
+        (
+            "Scan/Git ignore rules/Ignored only",
+            IgnoreMode::IgnoredOnly,
+        ),
+    ] {
+        let state_weak = Rc::downgrade(&app_state);
+        menu_button.add(
+            label,
+            enums::Shortcut::None,
+            menu::MenuFlag::Radio,
+            move |_| {
+                if let Some(state) = state_weak.upgrade() {
+                    state.borrow_mut().scan_options.ignore_mode = mode;
+                }
+            },
+        );
+
+        if mode == ignore_mode
+            && let Some(mut item) = menu_button.find_item(label)
+        {
+            item.set();
+        }
+    }
+
+    {
+        let state_weak = Rc::downgrade(&app_state);
+        menu_button.add(
+            "Scan/Filters...",
+            enums::Shortcut::None,
+            menu::MenuFlag::Normal,
+            move |_| {
+                if let Some(state) = state_weak.upgrade() {
+                    handle_filter_settings(&state.borrow(), state_weak.clone());
+                }
+            },
+        );
+    }
+
+    {
+        let state_weak = Rc::downgrade(&app_state);
+        menu_button.add(
+            "Scan/Find duplicates",
+            enums::Shortcut::None,
+            menu::MenuFlag::Normal,
+            move |_| {
+                if let Some(state) = state_weak.upgrade() {
+                    handle_find_duplicates(&mut state.borrow_mut());
+                }
+            },
+        );
+    }
+
+    {
+        let state_weak = Rc::downgrade(&app_state);
+        menu_button.add(
+            "Scan/Settings...",
+            enums::Shortcut::None,
+            menu::MenuFlag::Normal,
+            move |_| {
+                if let Some(state) = state_weak.upgrade() {
+                    handle_scan_settings(&state.borrow(), state_weak.clone());
+                }
+            },
+        );
+    }
+
     // Use weak reference for resize handler
     let treemap_weak = Rc::downgrade(&app_state);
     wind.handle(move |_, ev| {
-        if ev == enums::Event::Resize {
-            if let Some(state) = treemap_weak.upgrade() {
-                state.borrow_mut().treemap.handle_resize();
-            }
+        if ev == enums::Event::Resize
+            && let Some(state) = treemap_weak.upgrade()
+        {
+            state.borrow_mut().treemap.handle_resize();
         }
         true
     });
@@ -117,6 +559,45 @@ fn main() {
         });
     }
 
+    // errors button callback
+    {
+        let state_weak = Rc::downgrade(&app_state);
+        app_state.borrow_mut().errors_button.set_callback(move |_| {
+            if let Some(state) = state_weak.upgrade() {
+                let state = state.borrow();
+                show_scan_summary(&state.scan_errors, &state.excluded_paths);
+            }
+        });
+    }
+
+    // largest files callbacks
+    {
+        let state_weak = Rc::downgrade(&app_state);
+        app_state.borrow_mut().files_sort.set_callback(move |_| {
+            if let Some(state) = state_weak.upgrade() {
+                refill_largest_files(&mut state.borrow_mut());
+            }
+        });
+    }
+    {
+        let state_weak = Rc::downgrade(&app_state);
+        app_state.borrow_mut().files_list.set_callback(move |list| {
+            if let Some(state) = state_weak.upgrade() {
+                handle_largest_file_click(&mut state.borrow_mut(), list.value());
+            }
+        });
+    }
+
+    // cancel button callback
+    {
+        let state_weak = Rc::downgrade(&app_state);
+        app_state.borrow_mut().cancel_button.set_callback(move |_| {
+            if let Some(state) = state_weak.upgrade() {
+                handle_cancel_button(&mut state.borrow_mut());
+            }
+        });
+    }
+
     wind.end();
     wind.show();
 
@@ -124,12 +605,24 @@ fn main() {
     update_progress_bar(&mut app_state.borrow_mut().progress, 0);
 
     // handle command line arguments
-    let args: Vec<String> = env::args().collect();
-    if args.len() > 1 {
-        let path = &args[1];
-
+    if let Some(snapshot) = args.snapshot {
+        // open the snapshot once the UI loop runs
+        let state_weak = Rc::downgrade(&app_state);
+        app::add_timeout3(0.1, move |_| {
+            if let Some(state) = state_weak.upgrade() {
+                handle_open_snapshot(&mut state.borrow_mut(), Some(snapshot.clone()));
+            }
+        });
+    } else if let Some(import) = args.import {
+        let state_weak = Rc::downgrade(&app_state);
+        app::add_timeout3(0.1, move |_| {
+            if let Some(state) = state_weak.upgrade() {
+                handle_import(&mut state.borrow_mut(), Some(import.clone()));
+            }
+        });
+    } else if let Some(path) = &args.path {
         // set initial path if provided
-        if let Ok(selected_path) = PathBuf::from(path).canonicalize() {
+        if let Ok(selected_path) = path.canonicalize() {
             let mut state = app_state.borrow_mut();
             state.selected_path = selected_path;
             let text = format!("Selected: {}", state.selected_path.display());
@@ -146,21 +639,39 @@ fn main() {
         }
     }
 
-    app.run().unwrap();
+    // process messages from the scan thread
+    while app.wait() {
+        if let Some(msg) = receiver.recv() {
+            handle_message(&mut app_state.borrow_mut(), msg, Rc::downgrade(&app_state));
+        }
+    }
 }
 
 fn handle_folder_select(state: &mut AppState) {
+    if state.cancel_token.is_some() {
+        return;
+    }
+
     if let Some(path) = FileDialog::new().set_directory(".").pick_folder() {
         let text = format!("Selected: {}", path.display());
         state.status_text.set_label(&text);
         state.selected_path = path;
         state.treemap.clear();
+        state.largest_files.clear();
+        refill_largest_files(state);
+        state.empty_dirs.clear();
+        state.empty_files.clear();
+        state.skipped_mounts.clear();
+        state.scanned = None;
+        state.live = false;
+        set_scan_summary(state, Vec::new(), Vec::new());
         state.scan_button.activate();
     }
 }
 
 fn handle_scan_button(state: &mut AppState) {
     let path = state.selected_path.clone();
+    let options = state.scan_options.clone();
     if !path.exists() || !path.is_dir() {
         state
             .status_text
@@ -168,29 +679,429 @@ fn handle_scan_button(state: &mut AppState) {
         return;
     }
 
-    state.scan_button.deactivate();
+    let cancel = begin_task(state);
+
+    // run the scan on a worker thread so the window stays responsive
+    let sender = state.sender;
+    thread::spawn(move || {
+        let progress_callback = |progress: &ScanProgress| {
+            sender.send(Message::Progress(progress.clone()));
+        };
+
+        let scanned_at = SystemTime::now();
+        match scan_folder_hierarchy(&path, &options, &cancel, Some(progress_callback)) {
+            Ok(mut result) => {
+                result.root.sort_children();
+                sender.send(Message::Finished(Snapshot {
+                    scanned_at,
+                    options,
+                    result,
+                }));
+            }
+            Err(e) if e.is::<ScanCancelled>() => sender.send(Message::Cancelled),
+            Err(e) => sender.send(Message::Failed(e.to_string())),
+        }
+    });
+}
+
+// `path` comes from the command line, otherwise a file dialog asks for it
+fn handle_open_snapshot(state: &mut AppState, path: Option<PathBuf>) {
+    if state.cancel_token.is_some() {
+        return;
+    }
+
+    let path = match path {
+        Some(path) => path,
+        None => match FileDialog::new()
+            .add_filter("Snapshot", &[snapshot::EXTENSION])
+            .pick_file()
+        {
+            Some(path) => path,
+            None => return,
+        },
+    };
+
+    state
+        .status_text
+        .set_label(&format!("Opening {}...", path.display()));
+
+    // big snapshots take a moment to decode
+    let sender = state.sender;
+    thread::spawn(move || match Snapshot::load(&path) {
+        Ok(snapshot) => sender.send(Message::Opened(snapshot)),
+        Err(e) => sender.send(Message::Failed(format!("{}: {}", path.display(), e))),
+    });
+}
+
+// like a snapshot, but read from the output of ncdu or du (see `import`)
+fn handle_import(state: &mut AppState, path: Option<PathBuf>) {
+    if state.cancel_token.is_some() {
+        return;
+    }
+
+    let path = match path {
+        Some(path) => path,
+        // du output usually has no extension, so no filter
+        None => match FileDialog::new().pick_file() {
+            Some(path) => path,
+            None => return,
+        },
+    };
+
+    state
+        .status_text
+        .set_label(&format!("Importing {}...", path.display()));
+
+    // the current options decide how much of the tree is kept
+    let options = state.scan_options.clone();
+    let sender = state.sender;
+    thread::spawn(move || match import_file(&path, &options) {
+        Ok(snapshot) => sender.send(Message::Opened(snapshot)),
+        Err(e) => sender.send(Message::Failed(format!("{}: {}", path.display(), e))),
+    });
+}
+
+fn handle_save_snapshot(state: &mut AppState) {
+    let Some((scanned_at, options)) = state.scanned.clone() else {
+        state.status_text.set_label("Scan a folder first!");
+        return;
+    };
+    let Some(root) = state.treemap.with_root(|root, _| root.clone()) else {
+        return;
+    };
+
+    let Some(path) = FileDialog::new()
+        .add_filter("Snapshot", &[snapshot::EXTENSION])
+        .set_file_name(format!("{}.{}", root.name, snapshot::EXTENSION))
+        .save_file()
+    else {
+        return;
+    };
+
+    let snapshot = Snapshot {
+        scanned_at,
+        options,
+        result: ScanResult {
+            root,
+            errors: state.scan_errors.clone(),
+            skipped_mounts: state.skipped_mounts.clone(),
+            excluded: state.excluded_paths.clone(),
+            largest_files: state.largest_files.clone(),
+            empty_dirs: state.empty_dirs.clone(),
+            empty_files: state.empty_files.clone(),
+        },
+    };
+
+    match snapshot.save(&path) {
+        Ok(()) => state
+            .status_text
+            .set_label(&format!("Saved snapshot to {}", path.display())),
+        Err(e) => {
+            state
+                .status_text
+                .set_label(&format!("Error: failed to save {}: {}", path.display(), e))
+        }
+    }
+}
+
+fn handle_export(state: &mut AppState, format: ExportFormat) {
+    let Some(name) = state.treemap.with_root(|root, _| root.name.clone()) else {
+        state.status_text.set_label("Scan a folder first!");
+        return;
+    };
+
+    let Some(path) = FileDialog::new()
+        .add_filter(format.to_string(), &[format.extension()])
+        .set_file_name(format!("{}.{}", name, format.extension()))
+        .save_file()
+    else {
+        return;
+    };
+
+    let result = state
+        .treemap
+        .with_root(|root, _| export_to_file(root, format, &path));
+    match result {
+        Some(Ok(())) => state
+            .status_text
+            .set_label(&format!("Exported to {}", path.display())),
+        Some(Err(e)) => state.status_text.set_label(&format!(
+            "Error: failed to export {}: {}",
+            path.display(),
+            e
+        )),
+        None => {}
+    }
+}
+
+// scan, open or import `source` and write the tree, returns the exit code
+fn run_export(target: &ExportTarget, source: Option<Source<'_>>, options: &ScanOptions) -> i32 {
+    let Some(source) = source else {
+        eprintln!("error: --export needs a folder to scan or a file to open or import");
+        return 2;
+    };
+
+    let (source, root): (&Path, Result<FolderNode, Box<dyn std::error::Error>>) = match source {
+        Source::Snapshot(path) => (
+            path,
+            Snapshot::load(path)
+                .map(|snapshot| snapshot.result.root)
+                .map_err(Into::into),
+        ),
+        Source::Import(path) => (
+            path,
+            import_file(path, options)
+                .map(|snapshot| snapshot.result.root)
+                .map_err(Into::into),
+        ),
+        Source::Folder(path) => {
+            let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+            let root = scan_folder_hierarchy(
+                &canonical,
+                options,
+                &CancelToken::new(),
+                None::<fn(&ScanProgress)>,
+            )
+            .map(|mut result| {
+                result.root.sort_children();
+                result.root
+            });
+            (path, root)
+        }
+    };
+
+    let root = match root {
+        Ok(root) => root,
+        Err(e) => {
+            eprintln!("error: {}: {}", source.display(), e);
+            return 1;
+        }
+    };
+
+    match export_to_file(&root, target.format, &target.path) {
+        Ok(()) => {
+            println!("Exported {} to {}", target.format, target.path.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("error: failed to export {}: {}", target.path.display(), e);
+            1
+        }
+    }
+}
+
+fn handle_find_duplicates(state: &mut AppState) {
+    if state.cancel_token.is_some() {
+        return;
+    }
+
+    // only what the shown tree holds, with the options it was made with
+    let (Some(path), Some((_, options))) = (
+        state.treemap.with_root(|root, _| root.path.clone()),
+        state.scanned.clone(),
+    ) else {
+        state.status_text.set_label("Scan a folder first!");
+        return;
+    };
+    let cancel = begin_task(state);
+
+    let sender = state.sender;
+    thread::spawn(move || {
+        let progress_callback = |progress: &ScanProgress| {
+            sender.send(Message::Progress(progress.clone()));
+        };
+
+        match find_duplicates(&path, &options, &cancel, Some(progress_callback)) {
+            Ok(groups) => sender.send(Message::Duplicates(groups)),
+            Err(e) if e.is::<ScanCancelled>() => sender.send(Message::Cancelled),
+            Err(e) => sender.send(Message::Failed(e.to_string())),
+        }
+    });
+}
+
+// swap the scan button for the cancel button while a background task runs
+fn begin_task(state: &mut AppState) -> CancelToken {
+    let cancel = CancelToken::new();
+    state.cancel_token = Some(cancel.clone());
+
+    state.scan_button.hide();
+    state.cancel_button.activate();
+    state.cancel_button.show();
     state.status_text.set_label("");
 
     // reset progress
     update_progress_bar(&mut state.progress, 0);
-    app::flush();
 
-    let progress_callback = |percentage: i32, message: &str| {
-        update_progress_bar(&mut state.progress, percentage);
-        state.status_text.set_label(message);
-        app::flush();
+    cancel
+}
+
+fn handle_size_mode(state: &mut AppState, mode: SizeMode) {
+    // also used for the pruning thresholds of the next scan
+    state.scan_options.size_mode = mode;
+    state.treemap.set_size_mode(mode);
+    refill_largest_files(state);
+}
+
+// like picking `mode` from the view menu
+fn select_size_mode(state: &mut AppState, mode: SizeMode) {
+    handle_size_mode(state, mode);
+    for (label, item_mode) in SIZE_MODE_ITEMS {
+        if let Some(mut item) = state.menu_button.find_item(label) {
+            if item_mode == mode {
+                item.set();
+            } else {
+                item.clear();
+            }
+        }
+    }
+}
+
+fn refill_largest_files(state: &mut AppState) {
+    let sort = state.files_sort.value();
+    let mode = state.scan_options.size_mode;
+    fill_largest_files(&mut state.files_list, &mut state.largest_files, sort, mode);
+}
+
+// browser lines start at 1, 0 means nothing is selected
+fn handle_largest_file_click(state: &mut AppState, line: i32) {
+    let paths: Vec<PathBuf> = usize::try_from(line - 1)
+        .ok()
+        .and_then(|index| state.largest_files.get(index))
+        .map(|file| file.path.clone())
+        .into_iter()
+        .collect();
+    state.treemap.select_paths(&paths);
+}
+
+fn handle_cancel_button(state: &mut AppState) {
+    if let Some(cancel) = &state.cancel_token {
+        cancel.cancel();
+        state.cancel_button.deactivate();
+        state.status_text.set_label("Cancelling scan...");
+    }
+}
+
+fn set_scan_summary(state: &mut AppState, errors: Vec<ScanError>, excluded: Vec<PathBuf>) {
+    state.scan_errors = errors;
+    state.excluded_paths = excluded;
+
+    // errors are more important than exclusions for the button label
+    let label = match (state.scan_errors.len(), state.excluded_paths.len()) {
+        (0, 0) => {
+            state.errors_button.hide();
+            return;
+        }
+        (0, count) => format!("{} excluded", count),
+        (1, _) => "1 error".to_string(),
+        (count, _) => format!("{} errors", count),
     };
+    state.errors_button.set_label(&label);
+    state.errors_button.show();
+}
 
-    match scan_folder_hierarchy(&path, Some(progress_callback)) {
-        Ok(mut tree) => {
-            tree.sort_children();
-            state.treemap.set_data(&tree);
+fn handle_scan_settings(state: &AppState, state_weak: Weak<RefCell<AppState>>) {
+    show_scan_settings(&state.scan_options, move |settings| {
+        if let Some(state) = state_weak.upgrade() {
+            let options = &mut state.borrow_mut().scan_options;
+            options.threads = settings.threads;
+            options.detail_threshold = settings.detail_threshold;
+            options.min_fraction = settings.min_fraction;
+            options.max_depth = settings.max_depth;
+            options.file_threshold = settings.file_threshold;
         }
-        Err(e) => {
+    });
+}
+
+fn handle_filter_settings(state: &AppState, state_weak: Weak<RefCell<AppState>>) {
+    let options = &state.scan_options;
+    show_filter_dialog(
+        &options.exclude,
+        &options.include,
+        move |exclude, include| {
+            if let Some(state) = state_weak.upgrade() {
+                let mut state = state.borrow_mut();
+                state.scan_options.exclude = exclude;
+                state.scan_options.include = include;
+            }
+        },
+    );
+}
+
+fn finish_scan(state: &mut AppState) {
+    state.cancel_token = None;
+    state.cancel_button.hide();
+    state.scan_button.show();
+    state.scan_button.activate();
+}
+
+fn show_snapshot(state: &mut AppState, snapshot: Snapshot) {
+    let result = snapshot.result;
+    state.treemap.set_data(&result.root);
+    state.largest_files = result.largest_files;
+    refill_largest_files(state);
+    state.empty_dirs = result.empty_dirs;
+    state.empty_files = result.empty_files;
+    state.skipped_mounts = result.skipped_mounts;
+    state.scanned = Some((snapshot.scanned_at, snapshot.options));
+    set_scan_summary(state, result.errors, result.excluded);
+}
+
+fn handle_message(state: &mut AppState, msg: Message, state_weak: Weak<RefCell<AppState>>) {
+    match msg {
+        Message::Progress(progress) => {
+            update_progress_bar(&mut state.progress, progress.percentage);
+            state.status_text.set_label(&format_progress(&progress));
+        }
+        Message::Finished(snapshot) => {
+            show_snapshot(state, snapshot);
+            state.live = true;
+            finish_scan(state);
+        }
+        Message::Opened(snapshot) => {
+            let root = &snapshot.result.root;
+            let text = format!(
+                "Snapshot of {} from {}",
+                root.path.display(),
+                format_date(snapshot.scanned_at)
+            );
+            // du output only has disk usage, nothing would show by apparent size
+            let no_apparent_sizes = root.size == 0 && root.allocated_size > 0;
+            state.selected_path = snapshot.result.root.path.clone();
+            show_snapshot(state, snapshot);
+            if no_apparent_sizes && state.scan_options.size_mode == SizeMode::Apparent {
+                select_size_mode(state, SizeMode::Allocated);
+            }
+            state.live = false;
+            update_progress_bar(&mut state.progress, 100);
+            state.status_text.set_label(&text);
+            state.scan_button.activate();
+        }
+        Message::Duplicates(groups) => {
+            let wasted: u64 = groups.iter().map(DuplicateGroup::wasted).sum();
+            state.status_text.set_label(&format!(
+                "Found {} duplicate groups wasting {}",
+                groups.len(),
+                format_size(wasted)
+            ));
+            update_progress_bar(&mut state.progress, 100);
+            finish_scan(state);
+
+            show_duplicates(groups, move |paths| {
+                if let Some(state) = state_weak.upgrade() {
+                    state.borrow_mut().treemap.select_paths(paths);
+                }
+            });
+        }
+        Message::Failed(e) => {
             state.status_text.set_label(&format!("Error: {}", e));
             update_progress_bar(&mut state.progress, 0);
+            finish_scan(state);
+        }
+        Message::Cancelled => {
+            // keep the previous treemap around
+            state.status_text.set_label("Scan cancelled");
+            update_progress_bar(&mut state.progress, 0);
+            finish_scan(state);
         }
     }
-
-    state.scan_button.activate();
 }
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::folder::SizeMode;
+use crate::theme::*;
+use crate::types::Tally;
+
+const PASSWD_FILE: &str = "/etc/passwd";
+const GROUP_FILE: &str = "/etc/group";
+
+// colors handed out to owners by id, root always gets the first one
+const OWNER_COLORS: &[u32] = &[
+    CATPPUCCIN_RED,
+    CATPPUCCIN_BLUE,
+    CATPPUCCIN_GREEN,
+    CATPPUCCIN_PEACH,
+    CATPPUCCIN_MAUVE,
+    CATPPUCCIN_TEAL,
+    CATPPUCCIN_YELLOW,
+    CATPPUCCIN_PINK,
+    CATPPUCCIN_SAPPHIRE,
+    CATPPUCCIN_FLAMINGO,
+];
+
+// bytes and file counts per owning uid and gid, empty where there are no owners
+#[derive(Debug, Clone, Default)]
+pub struct OwnerTable {
+    pub users: HashMap<u32, Tally>,
+    pub groups: HashMap<u32, Tally>,
+}
+
+impl OwnerTable {
+    #[cfg(unix)]
+    #[inline]
+    pub fn add_file(&mut self, metadata: &fs::Metadata, allocated_size: u64) {
+        use std::os::unix::fs::MetadataExt;
+
+        let tally = Tally {
+            size: metadata.len(),
+            allocated_size,
+            count: 1,
+        };
+        self.users.entry(metadata.uid()).or_default().merge(&tally);
+        self.groups.entry(metadata.gid()).or_default().merge(&tally);
+    }
+
+    #[cfg(not(unix))]
+    #[inline(always)]
+    pub fn add_file(&mut self, _metadata: &fs::Metadata, _allocated_size: u64) {}
+
+    pub fn merge(&mut self, other: &OwnerTable) {
+        merge_ids(&mut self.users, &other.users);
+        merge_ids(&mut self.groups, &other.groups);
+    }
+
+    // the user owning most of the space, used for coloring
+    pub fn dominant_user(&self, mode: SizeMode) -> Option<u32> {
+        self.users
+            .iter()
+            .filter(|(_, tally)| tally.size_in(mode) > 0)
+            .max_by_key(|(uid, tally)| (tally.size_in(mode), std::cmp::Reverse(**uid)))
+            .map(|(uid, _)| *uid)
+    }
+}
+
+fn merge_ids(into: &mut HashMap<u32, Tally>, from: &HashMap<u32, Tally>) {
+    for (id, tally) in from {
+        into.entry(*id).or_default().merge(tally);
+    }
+}
+
+#[inline]
+pub fn owner_color(uid: u32) -> u32 {
+    OWNER_COLORS[uid as usize % OWNER_COLORS.len()]
+}
+
+// user and group names from the local account databases
+#[derive(Debug, Clone, Default)]
+pub struct OwnerNames {
+    users: HashMap<u32, String>,
+    groups: HashMap<u32, String>,
+}
+
+impl OwnerNames {
+    // missing files (windows, containers, ...) just leave the ids unresolved
+    pub fn load() -> Self {
+        Self {
+            users: read_id_file(PASSWD_FILE),
+            groups: read_id_file(GROUP_FILE),
+        }
+    }
+
+    pub fn user(&self, uid: u32) -> String {
+        self.users
+            .get(&uid)
+            .cloned()
+            .unwrap_or_else(|| uid.to_string())
+    }
+
+    pub fn group(&self, gid: u32) -> String {
+        self.groups
+            .get(&gid)
+            .cloned()
+            .unwrap_or_else(|| gid.to_string())
+    }
+}
+
+// both files are `name:password:id:...` lines
+fn read_id_file(path: &str) -> HashMap<u32, String> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+
+    let mut names = HashMap::new();
+    for line in text.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split(':');
+        if let (Some(name), Some(id)) = (fields.next(), fields.nth(1))
+            && let Ok(id) = id.parse()
+        {
+            // the first entry for an id wins, like getpwuid
+            names.entry(id).or_insert_with(|| name.to_string());
+        }
+    }
+    names
+}
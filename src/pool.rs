@@ -0,0 +1,137 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+#[inline]
+pub fn default_thread_count() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+}
+
+struct Shared<T> {
+    queues: Vec<Mutex<VecDeque<T>>>,
+    pending: AtomicUsize, // jobs that are queued or running
+    queued: AtomicUsize,  // jobs that no worker has taken yet
+    idle_lock: Mutex<()>,
+    idle: Condvar,
+}
+
+impl<T> Shared<T> {
+    // taking the lock orders the wakeup after the check of a worker about to wait
+    #[inline]
+    fn wake(&self, all: bool) {
+        drop(self.idle_lock.lock().unwrap());
+        if all {
+            self.idle.notify_all();
+        } else {
+            self.idle.notify_one();
+        }
+    }
+}
+
+// handle given to jobs so they can queue more work on the pool
+pub struct Worker<'a, T> {
+    index: usize,
+    shared: &'a Shared<T>,
+}
+
+impl<T> Worker<'_, T> {
+    #[inline]
+    pub fn spawn(&self, job: T) {
+        // counted before it can be taken, so `queued` never drops below zero
+        self.shared.pending.fetch_add(1, Ordering::SeqCst);
+        self.shared.queued.fetch_add(1, Ordering::SeqCst);
+        self.shared.queues[self.index]
+            .lock()
+            .unwrap()
+            .push_back(job);
+        self.shared.wake(false);
+    }
+
+    fn next_job(&self) -> Option<T> {
+        // own work first (newest job, keeps the walk depth-first)
+        let own = self.shared.queues[self.index].lock().unwrap().pop_back();
+
+        // then steal the oldest job from another worker
+        let count = self.shared.queues.len();
+        let job = own.or_else(|| {
+            (1..count).find_map(|offset| {
+                self.shared.queues[(self.index + offset) % count]
+                    .lock()
+                    .unwrap()
+                    .pop_front()
+            })
+        });
+
+        if job.is_some() {
+            self.shared.queued.fetch_sub(1, Ordering::SeqCst);
+        }
+        job
+    }
+}
+
+// marks a job as finished even if it panics so the other workers can exit
+struct JobGuard<'a, T>(&'a Shared<T>);
+
+impl<T> Drop for JobGuard<'_, T> {
+    fn drop(&mut self) {
+        if self.0.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.wake(true);
+        }
+    }
+}
+
+// run `jobs` (and everything they spawn) on a work-stealing pool of `threads` workers
+pub fn run<T, F>(threads: usize, jobs: Vec<T>, work: F)
+where
+    T: Send,
+    F: Fn(T, &Worker<T>) + Sync,
+{
+    if jobs.is_empty() {
+        return;
+    }
+
+    let threads = threads.max(1);
+    let shared = Shared {
+        queues: (0..threads).map(|_| Mutex::new(VecDeque::new())).collect(),
+        pending: AtomicUsize::new(jobs.len()),
+        queued: AtomicUsize::new(jobs.len()),
+        idle_lock: Mutex::new(()),
+        idle: Condvar::new(),
+    };
+
+    // spread the initial jobs over all workers
+    for (i, job) in jobs.into_iter().enumerate() {
+        shared.queues[i % threads].lock().unwrap().push_back(job);
+    }
+
+    thread::scope(|scope| {
+        for index in 0..threads {
+            let shared = &shared;
+            let work = &work;
+
+            scope.spawn(move || {
+                let worker = Worker { index, shared };
+
+                loop {
+                    if let Some(job) = worker.next_job() {
+                        let _guard = JobGuard(shared);
+                        work(job, &worker);
+                    } else if shared.pending.load(Ordering::SeqCst) == 0 {
+                        break;
+                    } else {
+                        // sleep until a job is queued or the last one finished
+                        let mut lock = shared.idle_lock.lock().unwrap();
+                        while shared.queued.load(Ordering::SeqCst) == 0
+                            && shared.pending.load(Ordering::SeqCst) != 0
+                        {
+                            lock = shared.idle.wait(lock).unwrap();
+                        }
+                    }
+                }
+            });
+        }
+    });
+}
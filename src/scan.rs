@@ -1,23 +1,329 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
 use std::path::{Path, PathBuf};
-use std::sync::mpsc;
-use std::{fs, thread};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant};
+use std::{fmt, fs, io, thread};
 
-use crate::folder::FolderNode;
+use crate::filter::{FilterMatch, ScanFilter};
+use crate::folder::{FolderNode, NodeKind, SizeMode, TimeRange};
+use crate::ignore::{self, IgnoreMode, IgnoreStack};
+use crate::owners::OwnerTable;
+use crate::pool::{self, Worker};
+use crate::types::TypeTable;
 use crate::utils::format_size;
 
-const SCAN_THRESHOLD: u64 = 1024 * 1024; // 1MB 
-const SUBDIRECTORY_COUNT_THRESHOLD: usize = 10; // threshold for parallel scanning
-const THRESHOLD_FACTOR: f64 = 0.0001; // 0.01% of total size
-const MAX_CONCURRENT_THREADS: usize = 8; // limit for concurrent threads
+const DETAIL_THRESHOLD: u64 = 1024 * 1024; // 1MB
+const MIN_FRACTION: f64 = 0.0001; // 0.01% of total size
+const FILE_THRESHOLD: u64 = 1024 * 1024; // 1MB
+const LARGEST_FILES: usize = 100;
+
+// when symbolic links are followed instead of being counted by their own size
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    #[default]
+    Never,
+    WithinRoot, // only links pointing somewhere below the scanned folder
+    Always,
+}
+
+impl fmt::Display for SymlinkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Never => write!(f, "never"),
+            Self::WithinRoot => write!(f, "within-root"),
+            Self::Always => write!(f, "always"),
+        }
+    }
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(Self::Never),
+            "within-root" | "root" => Ok(Self::WithinRoot),
+            "always" => Ok(Self::Always),
+            _ => Err(format!("unknown symlink policy '{}'", s)),
+        }
+    }
+}
+
+// a `max-depth` setting, `none` (or 0) lifts the limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxDepth(pub Option<usize>);
+
+impl FromStr for MaxDepth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" | "0" => Ok(Self(None)),
+            _ => s
+                .parse()
+                .map(|depth| Self(Some(depth)))
+                .map_err(|_| format!("invalid depth '{}'", s)),
+        }
+    }
+}
+
+// a `min-fraction` setting, 1 or more would merge the whole tree
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MinFraction(pub f64);
+
+impl FromStr for MinFraction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse()
+            .ok()
+            .filter(|fraction| (0.0..1.0).contains(fraction))
+            .map(Self)
+            .ok_or_else(|| format!("invalid fraction '{}', expected at least 0 and below 1", s))
+    }
+}
+
+// a `threads` setting, the pool needs at least one worker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadCount(pub usize);
+
+impl FromStr for ThreadCount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse()
+            .ok()
+            .filter(|threads| *threads > 0)
+            .map(Self)
+            .ok_or_else(|| format!("invalid thread count '{}'", s))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    pub threads: usize,           // size of the worker pool
+    pub detail_threshold: u64,    // folders up to this size don't keep their subfolders
+    pub min_fraction: f64,        // children below this fraction of the total are merged
+    pub max_depth: Option<usize>, // deeper folders are merged into their ancestor
+    pub file_threshold: u64,      // files at least this size get their own node
+    pub largest_files: usize,     // length of the largest files list
+    pub size_mode: SizeMode,
+    pub one_file_system: bool, // don't descend into other mounted file systems
+    pub symlinks: SymlinkPolicy,
+    pub exclude: Vec<String>,    // glob patterns of entries to leave out
+    pub include: Vec<String>,    // glob patterns of files to keep (all if empty)
+    pub ignore_mode: IgnoreMode, // split by .gitignore/.ignore rules
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            threads: pool::default_thread_count(),
+            detail_threshold: DETAIL_THRESHOLD,
+            min_fraction: MIN_FRACTION,
+            max_depth: None,
+            file_threshold: FILE_THRESHOLD,
+            largest_files: LARGEST_FILES,
+            size_mode: SizeMode::default(),
+            one_file_system: false,
+            symlinks: SymlinkPolicy::default(),
+            exclude: Vec::new(),
+            include: Vec::new(),
+            ignore_mode: IgnoreMode::default(),
+        }
+    }
+}
+
+// why a path could not be (fully) scanned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanErrorKind {
+    PermissionDenied,
+    Vanished, // removed while the scan was running
+    Io,
+}
+
+impl From<&io::Error> for ScanErrorKind {
+    fn from(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            io::ErrorKind::NotFound => Self::Vanished,
+            _ => Self::Io,
+        }
+    }
+}
+
+impl fmt::Display for ScanErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PermissionDenied => write!(f, "Permission denied"),
+            Self::Vanished => write!(f, "Vanished"),
+            Self::Io => write!(f, "I/O error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanError {
+    pub path: PathBuf,
+    pub kind: ScanErrorKind,
+}
+
+impl ScanError {
+    #[inline]
+    fn new(path: PathBuf, error: &io::Error) -> Self {
+        Self {
+            path,
+            kind: error.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanResult {
+    pub root: FolderNode,
+    pub errors: Vec<ScanError>,
+    pub skipped_mounts: Vec<PathBuf>, // mount points left out by `one_file_system`
+    pub excluded: Vec<PathBuf>,       // entries matching an exclude pattern
+    pub largest_files: Vec<LargeFile>, // largest first
+    pub empty_dirs: Vec<PathBuf>,     // top-most directories with nothing but empty directories
+    pub empty_files: Vec<PathBuf>,    // zero-byte files
+}
+
+// an entry of the largest files list
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LargeFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub allocated_size: u64,
+}
+
+impl LargeFile {
+    #[inline(always)]
+    pub fn size_in(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Allocated => self.allocated_size,
+            _ => self.size,
+        }
+    }
+}
+
+// bounded min-heap keeping the `limit` largest files offered to it
+#[derive(Debug, Default)]
+pub struct LargestFiles {
+    limit: usize,
+    heap: BinaryHeap<Reverse<(u64, LargeFile)>>,
+}
+
+impl LargestFiles {
+    #[inline]
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    #[inline(always)]
+    fn would_keep(&self, size: u64) -> bool {
+        self.heap.len() < self.limit
+            || self
+                .heap
+                .peek()
+                .is_some_and(|Reverse((smallest, _))| size > *smallest)
+    }
+
+    pub fn offer(&mut self, size: u64, file: LargeFile) {
+        if !self.would_keep(size) {
+            return;
+        }
+        self.heap.push(Reverse((size, file)));
+        if self.heap.len() > self.limit {
+            self.heap.pop();
+        }
+    }
+
+    fn merge(&mut self, other: LargestFiles) {
+        for Reverse((size, file)) in other.heap {
+            self.offer(size, file);
+        }
+    }
+
+    // largest first
+    pub fn into_sorted(self) -> Vec<LargeFile> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((_, file))| file)
+            .collect()
+    }
+}
+
+// snapshot of a running scan passed to the progress callback
+#[derive(Debug, Clone, Default)]
+pub struct ScanProgress {
+    pub percentage: i32, // never goes down during a scan
+    pub dirs_discovered: u64,
+    pub dirs_completed: u64,
+    pub entries: u64,
+    pub bytes: u64,
+    pub entries_per_second: f64,
+    pub eta: Option<Duration>,
+    pub message: String,
+}
+
+// shared flag used to abort a running scan from another thread
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+pub struct ScanCancelled;
+
+impl fmt::Display for ScanCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Scan cancelled")
+    }
+}
+
+impl std::error::Error for ScanCancelled {}
+
+#[inline(always)]
+fn check_cancelled(cancel: &CancelToken) -> Result<(), Box<dyn std::error::Error>> {
+    if cancel.is_cancelled() {
+        return Err(Box::new(ScanCancelled));
+    }
+    Ok(())
+}
 
 pub fn scan_folder_hierarchy<F>(
     root_path: &Path,
+    options: &ScanOptions,
+    cancel: &CancelToken,
     mut progress_callback: Option<F>,
-) -> Result<FolderNode, Box<dyn std::error::Error>>
+) -> Result<ScanResult, Box<dyn std::error::Error>>
 where
-    F: FnMut(i32, &str),
+    F: FnMut(&ScanProgress),
 {
-    let start_time = std::time::Instant::now();
+    let start_time = Instant::now();
 
     let root_name = root_path
         .file_name()
@@ -26,9 +332,15 @@ where
         .to_string();
 
     println!("Starting scan for: {}", root_path.to_string_lossy());
-    let mut root_node = FolderNode::new(root_name, root_path.to_path_buf(), 0);
-    let total_size = fast_parallel_scan(&mut root_node, &mut progress_callback)?;
-    let threshold = (total_size as f64 * THRESHOLD_FACTOR) as u64;
+    let (mut result, mut progress) = fast_parallel_scan(
+        root_path,
+        root_name,
+        options,
+        cancel,
+        &mut progress_callback,
+    )?;
+    let total_size = result.root.size_in(options.size_mode.bytes());
+    let threshold = (result.root.size_in(options.size_mode) as f64 * options.min_fraction) as u64;
 
     println!("Scan completed in {:?}", start_time.elapsed());
     println!(
@@ -36,166 +348,688 @@ where
         total_size,
         format_size(total_size)
     );
+    if !result.errors.is_empty() {
+        println!("{} paths could not be scanned", result.errors.len());
+    }
+    if !result.skipped_mounts.is_empty() {
+        println!("Skipped {} mount points", result.skipped_mounts.len());
+    }
+    if !result.excluded.is_empty() {
+        println!("Excluded {} paths", result.excluded.len());
+    }
 
     if let Some(callback) = &mut progress_callback {
-        callback(
-            100,
-            &format!(
-                "Scanned {} in {:.2} seconds!",
-                format_size(total_size),
-                start_time.elapsed().as_secs_f64()
-            ),
+        progress.percentage = 100;
+        progress.eta = Some(Duration::ZERO);
+        progress.message = format!(
+            "Scanned {} in {:.2} seconds!",
+            format_size(total_size),
+            start_time.elapsed().as_secs_f64()
         );
+        if !result.skipped_mounts.is_empty() {
+            progress.message.push_str(&format!(
+                " ({} mount points skipped)",
+                result.skipped_mounts.len()
+            ));
+        }
+        callback(&progress);
     }
 
-    filter_hierarchy(&mut root_node, threshold);
+    filter_hierarchy(&mut result.root, threshold, options.size_mode);
+    result.root.cache_dominant();
 
-    Ok(root_node)
+    Ok(result)
 }
 
 #[inline]
-fn filter_hierarchy(node: &mut FolderNode, threshold: u64) {
-    // remove children below threshold
-    node.children.retain(|child| child.size >= threshold);
+pub fn filter_hierarchy(node: &mut FolderNode, threshold: u64, mode: SizeMode) {
+    // merge children below threshold (mount points stay visible)
+    node.merge_children(|child| !child.mount_point && child.size_in(mode) < threshold);
 
     // recursively filter remaining children
     for child in &mut node.children {
-        filter_hierarchy(child, threshold);
+        filter_hierarchy(child, threshold, mode);
     }
 }
 
-pub fn calculate_directory_size(dir_path: &Path) -> Result<u64, Box<dyn std::error::Error>> {
-    let mut total_size = 0u64;
+const ROOT_ID: usize = 0;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
 
-    fn visit_dir(dir: &Path, total: &mut u64) -> Result<(), Box<dyn std::error::Error>> {
-        let entries = fs::read_dir(dir)?;
+// a directory waiting to be read by the worker pool
+struct DirJob {
+    id: usize,
+    parent: usize,
+    path: PathBuf,
+    device: u64,          // file system the directory lives on
+    mount_point: bool,    // on another file system than its parent
+    ignores: IgnoreStack, // ignore rules of the parent directories
+    ignored: bool,        // matched by an ignore rule (or inside such a directory)
+    own_blocks: u64,      // disk usage of the directory entry itself, 0 if not counted
+}
 
-        for entry in entries {
-            let entry = entry?;
-            let path = entry.path();
+// state shared by all workers of a scan
+struct ScanContext {
+    next_id: AtomicUsize,
+    hard_links: Mutex<HashSet<(u64, u64)>>, // (device, inode) of files seen so far
+    one_file_system: bool,
+    symlinks: SymlinkPolicy,
+    root: PathBuf,      // canonical root for `SymlinkPolicy::WithinRoot`
+    root_path: PathBuf, // root as given, for relative filter paths
+    filter: ScanFilter,
+    ignore_mode: IgnoreMode,
+    size_mode: SizeMode,
+    file_threshold: u64,
+    largest_files: usize,
+    visited: Mutex<HashSet<(u64, u64)>>, // directories queued so far, breaks symlink loops
+}
 
-            // use metadata for checks (speeeeed)
-            // side effect is not following symlinks on unix
-            let metadata = entry.metadata()?;
+impl ScanContext {
+    // false if the directory was already queued (only tracked while following symlinks)
+    fn first_visit(&self, path: &Path, metadata: &fs::Metadata) -> bool {
+        if self.symlinks == SymlinkPolicy::Never {
+            return true;
+        }
 
-            if metadata.is_file() {
-                *total += metadata.len();
-            } else if metadata.is_dir() {
-                visit_dir(&path, total)?;
+        match file_id(path, metadata) {
+            Some(id) => self.visited.lock().unwrap().insert(id),
+            None => true,
+        }
+    }
+
+    #[inline]
+    fn follow_symlink(&self, path: &Path) -> Option<fs::Metadata> {
+        follow_symlink(self.symlinks, &self.root, path)
+    }
+}
+
+// metadata of the link target if `policy` allows following it, `root` is canonical
+pub fn follow_symlink(policy: SymlinkPolicy, root: &Path, path: &Path) -> Option<fs::Metadata> {
+    match policy {
+        SymlinkPolicy::Never => None,
+        SymlinkPolicy::WithinRoot => {
+            let target = fs::canonicalize(path).ok()?;
+            if !target.starts_with(root) {
+                return None;
             }
+            fs::metadata(target).ok()
         }
-        Ok(())
+        SymlinkPolicy::Always => fs::metadata(path).ok(),
     }
+}
 
-    visit_dir(dir_path, &mut total_size)?;
-    Ok(total_size)
+// result of reading a single directory (without its subdirectories)
+struct DirRecord {
+    parent: usize,
+    path: PathBuf,
+    file_size: u64,
+    allocated_size: u64,
+    hard_link_size: u64,
+    file_count: u64,
+    dir_count: u64,
+    other_count: u64,
+    times: TimeRange,
+    types: TypeTable,
+    owners: OwnerTable,
+    entries: u64,
+    subdirs: u64, // entries that were queued as directories
+    mount_point: bool,
+    skipped: bool,
+    files: Vec<FolderNode>, // entries big enough for their own leaf node
+    largest: LargestFiles,  // candidates for the largest files list
+    empty_files: Vec<PathBuf>,
+    errors: Vec<ScanError>,
+    excluded: Vec<PathBuf>,
+}
+
+impl DirRecord {
+    // the size is already part of this directory, the leaf only makes it visible
+    fn add_leaf(
+        &mut self,
+        context: &ScanContext,
+        path: &Path,
+        metadata: &fs::Metadata,
+        kind: NodeKind,
+    ) {
+        let mut leaf = FolderNode::new(
+            String::new(),
+            PathBuf::new(),
+            metadata.len(),
+            allocated_size(metadata),
+        );
+        if leaf.size_in(context.size_mode) < context.file_threshold {
+            return;
+        }
+
+        leaf.name = path
+            .file_name()
+            .unwrap_or_else(|| std::ffi::OsStr::new("unknown"))
+            .to_string_lossy()
+            .to_string();
+        leaf.path = path.to_path_buf();
+        leaf.kind = kind;
+        leaf.times.add(metadata);
+        match kind {
+            NodeKind::File => {
+                leaf.file_count = 1;
+                leaf.types.add_file(path, metadata, leaf.allocated_size);
+                leaf.owners.add_file(metadata, leaf.allocated_size);
+            }
+            _ => leaf.other_count = 1,
+        }
+        if hard_link_id(metadata).is_some() {
+            leaf.hard_link_size = leaf.size;
+        }
+        self.files.push(leaf);
+    }
+
+    #[inline]
+    fn add_large_file(&mut self, context: &ScanContext, path: &Path, metadata: &fs::Metadata) {
+        let file = LargeFile {
+            path: path.to_path_buf(),
+            size: metadata.len(),
+            allocated_size: allocated_size(metadata),
+        };
+        self.largest.offer(file.size_in(context.size_mode), file);
+    }
 }
 
 fn fast_parallel_scan<F>(
-    parent_node: &mut FolderNode,
+    root_path: &Path,
+    root_name: String,
+    options: &ScanOptions,
+    cancel: &CancelToken,
     progress_callback: &mut Option<F>,
-) -> Result<u64, Box<dyn std::error::Error>>
+) -> Result<(ScanResult, ScanProgress), Box<dyn std::error::Error>>
 where
-    F: FnMut(i32, &str),
+    F: FnMut(&ScanProgress),
 {
-    if let Some(callback) = progress_callback {
-        callback(20, &format!("Scanning: {}", parent_node.path.display()));
+    let context = ScanContext {
+        next_id: AtomicUsize::new(ROOT_ID + 1),
+        hard_links: Mutex::new(HashSet::new()),
+        one_file_system: options.one_file_system,
+        symlinks: options.symlinks,
+        root: fs::canonicalize(root_path).unwrap_or_else(|_| root_path.to_path_buf()),
+        root_path: root_path.to_path_buf(),
+        filter: ScanFilter::new(&options.exclude, &options.include)?,
+        ignore_mode: options.ignore_mode,
+        size_mode: options.size_mode.bytes(),
+        file_threshold: options.file_threshold,
+        largest_files: options.largest_files,
+        visited: Mutex::new(HashSet::new()),
+    };
+    if let Ok(metadata) = fs::metadata(root_path) {
+        context.first_visit(root_path, &metadata);
     }
+    let root_blocks = match fs::symlink_metadata(root_path) {
+        Ok(metadata) if options.ignore_mode.keeps(false) => allocated_size(&metadata),
+        _ => 0,
+    };
 
-    // get results
-    let result = scan_directory_fast(&parent_node.path)?;
-    let (total_size, children_data) = result;
-    parent_node.size = total_size;
+    let root_job = DirJob {
+        id: ROOT_ID,
+        parent: ROOT_ID,
+        path: root_path.to_path_buf(),
+        device: fs::metadata(root_path).map_or(0, |metadata| device_id(&metadata)),
+        mount_point: false,
+        ignores: IgnoreStack::default(),
+        ignored: false,
+        own_blocks: root_blocks,
+    };
 
-    // process children
-    for (child_path, child_size) in children_data {
-        let child_name = child_path
-            .file_name()
-            .unwrap_or_else(|| std::ffi::OsStr::new("unknown"))
-            .to_string_lossy()
-            .to_string();
+    // every directory is read exactly once by the pool, the records are
+    // collected here and assembled into a tree afterwards
+    let (tx, rx) = mpsc::channel();
+    let mut records: Vec<Option<DirRecord>> = Vec::new();
+    let mut largest = LargestFiles::new(options.largest_files);
+    let mut progress = ScanProgress::default();
+    let mut current_path = root_path.to_path_buf();
+
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let tx = tx;
+            pool::run(options.threads, vec![root_job], |job, worker| {
+                if cancel.is_cancelled() {
+                    return;
+                }
+
+                let id = job.id;
+                tx.send((id, scan_directory(job, &context, worker))).ok();
+            });
+        });
 
-        let mut child_node = FolderNode::new(child_name, child_path.clone(), child_size);
+        let start_time = Instant::now();
+        let mut last_progress = start_time;
+
+        loop {
+            match rx.recv_timeout(PROGRESS_INTERVAL) {
+                Ok((id, mut record)) => {
+                    progress.dirs_completed += 1;
+                    progress.entries += record.entries;
+                    progress.bytes += record.file_size;
+                    current_path.clone_from(&record.path);
+                    largest.merge(std::mem::take(&mut record.largest));
+
+                    if records.len() <= id {
+                        records.resize_with(id + 1, || None);
+                    }
+                    records[id] = Some(record);
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
 
-        // recursively scan big children
-        if child_size > SCAN_THRESHOLD {
-            if let Some(callback) = progress_callback {
-                callback(80, &format!("Deep scanning: {}", child_path.display()));
+            if let Some(callback) = progress_callback
+                && last_progress.elapsed() >= PROGRESS_INTERVAL
+            {
+                progress.dirs_discovered = context.next_id.load(Ordering::Relaxed) as u64;
+                update_progress(&mut progress, start_time.elapsed());
+                progress.message = format!("Scanning: {}", current_path.display());
+                callback(&progress);
+                last_progress = Instant::now();
             }
-            fast_parallel_scan(&mut child_node, progress_callback)?;
         }
+    });
+
+    check_cancelled(cancel)?;
+
+    progress.dirs_discovered = progress.dirs_completed;
+    let mut result = build_tree(records, root_name, options);
+    result.largest_files = largest.into_sorted();
+
+    Ok((result, progress))
+}
+
+// derive percentage, rate and ETA from the directories found so far
+fn update_progress(progress: &mut ScanProgress, elapsed: Duration) {
+    let discovered = progress.dirs_discovered.max(1);
+    let completed = progress.dirs_completed.min(discovered);
+
+    // new directories keep being discovered, so never report 100% early
+    // and never let the bar move backwards
+    let percentage = (completed * 99 / discovered) as i32;
+    progress.percentage = progress.percentage.max(percentage);
 
-        parent_node.add_child(child_node);
+    let seconds = elapsed.as_secs_f64();
+    if seconds > 0.0 {
+        progress.entries_per_second = progress.entries as f64 / seconds;
     }
 
-    Ok(total_size)
+    let dirs_per_second = completed as f64 / seconds.max(f64::EPSILON);
+    progress.eta = (dirs_per_second > 0.0)
+        .then(|| Duration::from_secs_f64((discovered - completed) as f64 / dirs_per_second));
 }
 
-type ScanResult = Result<(u64, Vec<(PathBuf, u64)>), Box<dyn std::error::Error>>;
+// read a single directory, summing its files and queueing its subdirectories
+fn scan_directory(job: DirJob, context: &ScanContext, worker: &Worker<DirJob>) -> DirRecord {
+    let mut record = DirRecord {
+        parent: job.parent,
+        path: job.path,
+        file_size: 0,
+        // a directory's own blocks belong to its node, exports and imports rely on that
+        allocated_size: job.own_blocks,
+        hard_link_size: 0,
+        file_count: 0,
+        dir_count: 0,
+        other_count: 0,
+        times: TimeRange::default(),
+        types: TypeTable::default(),
+        owners: OwnerTable::default(),
+        entries: 0,
+        subdirs: 0,
+        mount_point: job.mount_point,
+        skipped: false,
+        files: Vec::new(),
+        largest: LargestFiles::new(context.largest_files),
+        empty_files: Vec::new(),
+        errors: Vec::new(),
+        excluded: Vec::new(),
+    };
 
-fn scan_directory_fast(dir_path: &Path) -> ScanResult {
-    let mut total_size = 0u64;
-    let mut children = Vec::new();
+    // leave other file systems alone, the node itself still marks where they are
+    if job.mount_point && context.one_file_system {
+        record.skipped = true;
+        return record;
+    }
 
-    // read directory entries in one go
-    let entries: Vec<_> = fs::read_dir(dir_path)
-        .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)?;
+    let entries = match fs::read_dir(&record.path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            record.errors.push(ScanError::new(record.path.clone(), &e));
+            return record;
+        }
+    };
 
-    // process files first
-    let mut file_size = 0u64;
-    let mut directories = Vec::new();
+    // everything below an ignored directory is ignored, no need for more rules
+    let ignores = if context.ignore_mode == IgnoreMode::Off || job.ignored {
+        job.ignores
+    } else {
+        job.ignores.enter(&record.path)
+    };
 
     for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                record.errors.push(ScanError::new(record.path.clone(), &e));
+                continue;
+            }
+        };
+        record.entries += 1;
+
         let path = entry.path();
-        if path.is_file() {
-            if let Ok(metadata) = entry.metadata() {
-                file_size += metadata.len();
+
+        // use metadata for checks (speeeeed), this never follows symlinks
+        let mut metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                record.errors.push(ScanError::new(path, &e));
+                continue;
             }
-        } else if path.is_dir() {
-            directories.push(path);
+        };
+
+        // links that aren't followed are counted by their own size below
+        if metadata.file_type().is_symlink()
+            && let Some(target) = context.follow_symlink(&path)
+        {
+            metadata = target;
+        }
+        let is_dir = metadata.is_dir();
+
+        // excluded directories are never read
+        match context.filter.check(&context.root_path, &path, is_dir) {
+            FilterMatch::Keep => {}
+            FilterMatch::Excluded => {
+                record.excluded.push(path);
+                continue;
+            }
+            FilterMatch::NotIncluded => continue,
         }
-    }
 
-    total_size += file_size;
+        let ignored = match context.ignore_mode {
+            IgnoreMode::Off => false,
+            // git doesn't track its own repository
+            IgnoreMode::NotIgnored if is_dir && ignore::is_git_dir(&entry.file_name()) => continue,
+            _ => job.ignored || ignores.is_ignored(&path, is_dir),
+        };
+        let counted = context.ignore_mode.keeps(ignored);
 
-    // process directories (using multiple threads if above threshold)
-    if directories.len() > SUBDIRECTORY_COUNT_THRESHOLD {
-        // use thread pool
-        let (tx, rx) = mpsc::channel();
-        let mut handles = Vec::new();
+        if metadata.is_file() {
+            if !counted {
+                continue;
+            }
 
-        for dir in directories.into_iter().take(MAX_CONCURRENT_THREADS) {
-            // limit concurrent threads
-            let tx = tx.clone();
-            let handle = thread::spawn(move || {
-                let size = calculate_directory_size(&dir).unwrap_or(0);
-                tx.send((dir, size)).unwrap();
+            // count every inode only once, no matter how many links point to it
+            if let Some(inode) = hard_link_id(&metadata) {
+                if !context.hard_links.lock().unwrap().insert(inode) {
+                    continue;
+                }
+                record.hard_link_size += metadata.len();
+            }
+
+            record.file_size += metadata.len();
+            record.allocated_size += allocated_size(&metadata);
+            record.file_count += 1;
+            record.times.add(&metadata);
+            record
+                .types
+                .add_file(&path, &metadata, allocated_size(&metadata));
+            record.owners.add_file(&metadata, allocated_size(&metadata));
+            record.add_leaf(context, &path, &metadata, NodeKind::File);
+            record.add_large_file(context, &path, &metadata);
+            if metadata.len() == 0 {
+                record.empty_files.push(path);
+            }
+        } else if is_dir {
+            // ignored directories can't contain anything that isn't ignored
+            if ignored && !counted {
+                continue;
+            }
+
+            // reached before through a symlink (or is one of our ancestors)
+            if !context.first_visit(&path, &metadata) {
+                continue;
+            }
+
+            // directories take up disk space themselves (like `du` counts them)
+            let own_blocks = if counted {
+                record.dir_count += 1;
+                allocated_size(&metadata)
+            } else {
+                0
+            };
+            let device = device_id(&metadata);
+            record.subdirs += 1;
+            worker.spawn(DirJob {
+                id: context.next_id.fetch_add(1, Ordering::Relaxed),
+                parent: job.id,
+                path,
+                device,
+                mount_point: device != job.device,
+                ignores: ignores.clone(),
+                ignored,
+                own_blocks,
             });
-            handles.push(handle);
+        } else if counted {
+            // links that aren't followed count by their own size, like special files
+            let kind = if metadata.file_type().is_symlink() {
+                NodeKind::Symlink
+            } else {
+                NodeKind::Other
+            };
+            record.file_size += metadata.len();
+            record.allocated_size += allocated_size(&metadata);
+            record.other_count += 1;
+            record.times.add(&metadata);
+            record.add_leaf(context, &path, &metadata, kind);
         }
+    }
+
+    record
+}
+
+// assemble the records into a tree, summing sizes from the leaves up
+fn build_tree(
+    records: Vec<Option<DirRecord>>,
+    root_name: String,
+    options: &ScanOptions,
+) -> ScanResult {
+    // the detail threshold is given in bytes
+    let mode = options.size_mode.bytes();
+    let mut errors = Vec::new();
+    let mut skipped_mounts = Vec::new();
+    let mut excluded = Vec::new();
+    let mut empty_files = Vec::new();
+    let mut empty = Vec::with_capacity(records.len());
+    let mut parents = Vec::with_capacity(records.len());
+    let mut depths: Vec<usize> = Vec::with_capacity(records.len());
+    let mut nodes: Vec<Option<FolderNode>> = Vec::with_capacity(records.len());
+
+    for (id, record) in records.into_iter().enumerate() {
+        let Some(mut record) = record else {
+            parents.push(ROOT_ID);
+            depths.push(0);
+            empty.push(false);
+            nodes.push(None);
+            continue;
+        };
+
+        // parents always come first
+        let depth = if id == ROOT_ID {
+            0
+        } else {
+            depths[record.parent] + 1
+        };
 
-        drop(tx); // close sender
+        let name = if id == ROOT_ID {
+            root_name.clone()
+        } else {
+            record
+                .path
+                .file_name()
+                .unwrap_or_else(|| std::ffi::OsStr::new("unknown"))
+                .to_string_lossy()
+                .to_string()
+        };
 
-        // collect results
-        while let Ok((dir, size)) = rx.recv() {
-            total_size += size;
-            children.push((dir, size));
+        // only directories so far, their own contents are checked below
+        let only_dirs = record.entries == record.subdirs
+            && record.errors.is_empty()
+            && !record.mount_point
+            && !record.skipped;
+
+        let mut node = FolderNode::new(name, record.path, record.file_size, record.allocated_size);
+        node.hard_link_size = record.hard_link_size;
+        node.file_count = record.file_count;
+        node.dir_count = record.dir_count;
+        node.other_count = record.other_count;
+        node.times = record.times;
+        node.types = record.types;
+        node.owners = record.owners;
+        node.incomplete = !record.errors.is_empty();
+        node.mount_point = record.mount_point;
+        node.skipped = record.skipped;
+        node.children.append(&mut record.files);
+        errors.append(&mut record.errors);
+        excluded.append(&mut record.excluded);
+        empty_files.append(&mut record.empty_files);
+
+        if record.skipped {
+            skipped_mounts.push(node.path.clone());
         }
 
-        // wait for all threads
-        for handle in handles {
-            handle.join().unwrap();
+        parents.push(record.parent);
+        depths.push(depth);
+        empty.push(only_dirs);
+        nodes.push(Some(node));
+    }
+
+    // a directory with anything but empty directories in it makes its parent non-empty,
+    // only the top-most empty directory below the root is reported
+    for id in (ROOT_ID + 1..nodes.len()).rev() {
+        if !empty[id] {
+            empty[parents[id]] = false;
         }
-    } else {
-        // sequential for few directories
-        for dir in directories {
-            let size = calculate_directory_size(&dir).unwrap_or(0);
-            total_size += size;
-            children.push((dir, size));
+    }
+    let empty_dirs = (ROOT_ID + 1..nodes.len())
+        .filter(|&id| empty[id] && (parents[id] == ROOT_ID || !empty[parents[id]]))
+        .filter_map(|id| nodes[id].as_ref().map(|node| node.path.clone()))
+        .collect();
+
+    // children always get a higher id than their parent, so walking the ids
+    // backwards finishes every subtree before it is attached to its parent
+    for id in (ROOT_ID + 1..nodes.len()).rev() {
+        let Some(mut node) = nodes[id].take() else {
+            continue;
+        };
+
+        // don't keep the structure of small or too deep folders
+        if reached_max_depth(options, depths[id]) {
+            node.merge_children(|_| true);
+        } else if node.size_in(mode) <= options.detail_threshold {
+            node.merge_children(|child| !child.mount_point);
+        }
+
+        if let Some(parent) = nodes[parents[id]].as_mut() {
+            parent.size += node.size;
+            parent.allocated_size += node.allocated_size;
+            parent.hard_link_size += node.hard_link_size;
+            parent.file_count += node.file_count;
+            parent.dir_count += node.dir_count;
+            parent.other_count += node.other_count;
+            parent.times.merge(&node.times);
+            parent.types.merge(&node.types);
+            parent.owners.merge(&node.owners);
+            parent.incomplete |= node.incomplete;
+            parent.add_child(node);
         }
     }
 
-    Ok((total_size, children))
+    let mut root_node = nodes[ROOT_ID]
+        .take()
+        .unwrap_or_else(|| FolderNode::new(root_name, PathBuf::new(), 0, 0));
+    if reached_max_depth(options, 0) {
+        root_node.merge_children(|_| true);
+    }
+
+    ScanResult {
+        root: root_node,
+        errors,
+        skipped_mounts,
+        excluded,
+        largest_files: Vec::new(),
+        empty_dirs,
+        empty_files,
+    }
+}
+
+#[inline(always)]
+pub fn reached_max_depth(options: &ScanOptions, depth: usize) -> bool {
+    options
+        .max_depth
+        .is_some_and(|max_depth| depth >= max_depth)
+}
+
+// space actually used on disk (sparse files use less, small files use more)
+#[cfg(unix)]
+#[inline(always)]
+fn allocated_size(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    // st_blocks is always in 512 byte units
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+#[inline(always)]
+fn allocated_size(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+// (device, inode) of files that have more than one hard link
+#[cfg(unix)]
+#[inline(always)]
+pub fn hard_link_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+#[inline(always)]
+pub fn hard_link_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+// file system a path lives on, directories with a different one are mount points
+#[cfg(unix)]
+#[inline(always)]
+pub fn device_id(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.dev()
+}
+
+#[cfg(not(unix))]
+#[inline(always)]
+pub fn device_id(_metadata: &fs::Metadata) -> u64 {
+    0
+}
+
+// identity of a file or directory, to notice it when a symlink leads to it again
+#[cfg(unix)]
+#[inline(always)]
+pub fn file_id(_path: &Path, metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+pub fn file_id(path: &Path, _metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    // no inodes here, fall back to the resolved path
+    let mut hasher = DefaultHasher::new();
+    fs::canonicalize(path).ok()?.hash(&mut hasher);
+    Some((0, hasher.finish()))
 }
@@ -0,0 +1,816 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::folder::{FolderNode, NodeKind, SizeMode, TimeRange};
+use crate::ignore::IgnoreMode;
+use crate::owners::OwnerTable;
+use crate::scan::{LargeFile, ScanError, ScanErrorKind, ScanOptions, ScanResult, SymlinkPolicy};
+use crate::types::{FileCategory, Tally, TypeTable};
+
+pub const EXTENSION: &str = "fscan";
+
+const MAGIC: &[u8; 8] = b"FSCANSNP";
+// bump on every change to the layout below, older files are rejected
+const VERSION: u64 = 2;
+
+// how a node's path is stored relative to its parent's
+const PATH_CHILD: u8 = 0; // parent path joined with the name
+const PATH_PARENT: u8 = 1; // same as the parent (merged small items)
+const PATH_FULL: u8 = 2;
+
+const FLAG_INCOMPLETE: u8 = 1;
+const FLAG_MOUNT_POINT: u8 = 2;
+const FLAG_SKIPPED: u8 = 4;
+
+// deeper trees are refused when reading, a path can't get anywhere near this
+const MAX_NESTING: usize = 4096;
+
+// a finished scan together with how and when it was made
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub scanned_at: SystemTime,
+    pub options: ScanOptions,
+    pub result: ScanResult,
+}
+
+impl Snapshot {
+    // numbers are LEB128 varints, strings and lists are prefixed by their length
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    fn write_to<W: Write>(&self, out: W) -> io::Result<()> {
+        let mut writer = Writer(out);
+        writer.0.write_all(MAGIC)?;
+        writer.u64(VERSION)?;
+        writer.time(Some(self.scanned_at))?;
+        writer.options(&self.options)?;
+        writer.result(&self.result)?;
+        writer.0.flush()
+    }
+
+    fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader { data, pos: 0 };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a folder-scan snapshot"));
+        }
+        let version = reader.u64()?;
+        if version != VERSION {
+            return Err(invalid(&format!(
+                "unsupported snapshot version {}",
+                version
+            )));
+        }
+
+        let scanned_at = reader.time()?.unwrap_or(UNIX_EPOCH);
+        let options = reader.options()?;
+        let result = reader.result()?;
+        if reader.pos != data.len() {
+            return Err(invalid("trailing data after the snapshot"));
+        }
+
+        Ok(Self {
+            scanned_at,
+            options,
+            result,
+        })
+    }
+}
+
+#[inline]
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+struct Writer<W: Write>(W);
+
+impl<W: Write> Writer<W> {
+    fn u64(&mut self, mut value: u64) -> io::Result<()> {
+        let mut buffer = [0u8; 10];
+        let mut len = 0;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                buffer[len] = byte;
+                len += 1;
+                break;
+            }
+            buffer[len] = byte | 0x80;
+            len += 1;
+        }
+        self.0.write_all(&buffer[..len])
+    }
+
+    #[inline]
+    fn u8(&mut self, value: u8) -> io::Result<()> {
+        self.0.write_all(&[value])
+    }
+
+    #[inline]
+    fn str(&mut self, value: &str) -> io::Result<()> {
+        self.u64(value.len() as u64)?;
+        self.0.write_all(value.as_bytes())
+    }
+
+    // paths are stored as UTF-8, anything else is replaced
+    #[inline]
+    fn path(&mut self, path: &Path) -> io::Result<()> {
+        self.str(&path.to_string_lossy())
+    }
+
+    fn paths(&mut self, paths: &[PathBuf]) -> io::Result<()> {
+        self.u64(paths.len() as u64)?;
+        paths.iter().try_for_each(|path| self.path(path))
+    }
+
+    fn strings(&mut self, values: &[String]) -> io::Result<()> {
+        self.u64(values.len() as u64)?;
+        values.iter().try_for_each(|value| self.str(value))
+    }
+
+    // 0 for none, 1 after or 2 before the epoch, followed by the seconds and nanos
+    fn time(&mut self, time: Option<SystemTime>) -> io::Result<()> {
+        let Some(time) = time else {
+            return self.u8(0);
+        };
+        let (sign, duration) = match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => (1, duration),
+            Err(e) => (2, e.duration()),
+        };
+        self.u8(sign)?;
+        self.u64(duration.as_secs())?;
+        self.u64(duration.subsec_nanos() as u64)
+    }
+
+    fn tally(&mut self, tally: &Tally) -> io::Result<()> {
+        self.u64(tally.size)?;
+        self.u64(tally.allocated_size)?;
+        self.u64(tally.count)
+    }
+
+    fn ids(&mut self, ids: &std::collections::HashMap<u32, Tally>) -> io::Result<()> {
+        self.u64(ids.len() as u64)?;
+        ids.iter().try_for_each(|(id, tally)| {
+            self.u64(*id as u64)?;
+            self.tally(tally)
+        })
+    }
+
+    fn options(&mut self, options: &ScanOptions) -> io::Result<()> {
+        self.u64(options.threads as u64)?;
+        self.u64(options.detail_threshold)?;
+        self.u64(options.min_fraction.to_bits())?;
+        match options.max_depth {
+            Some(depth) => self.u64(depth as u64 + 1)?,
+            None => self.u64(0)?,
+        }
+        self.u64(options.file_threshold)?;
+        self.u64(options.largest_files as u64)?;
+        self.u8(size_mode_tag(options.size_mode))?;
+        self.u8(options.one_file_system as u8)?;
+        self.u8(symlink_tag(options.symlinks))?;
+        self.strings(&options.exclude)?;
+        self.strings(&options.include)?;
+        self.u8(ignore_tag(options.ignore_mode))
+    }
+
+    // nodes in pre-order, without recursion so any tree a scan made can be saved
+    fn tree(&mut self, root: &FolderNode) -> io::Result<()> {
+        let mut pending = vec![(root, None)];
+        while let Some((node, parent)) = pending.pop() {
+            self.node(node, parent)?;
+            pending.extend(
+                node.children
+                    .iter()
+                    .rev()
+                    .map(|child| (child, Some(node.path.as_path()))),
+            );
+        }
+        Ok(())
+    }
+
+    // everything but the children, which follow it
+    fn node(&mut self, node: &FolderNode, parent: Option<&Path>) -> io::Result<()> {
+        self.str(&node.name)?;
+        match parent {
+            Some(parent) if node.path == parent => self.u8(PATH_PARENT)?,
+            Some(parent) if node.path == parent.join(&node.name) => self.u8(PATH_CHILD)?,
+            _ => {
+                self.u8(PATH_FULL)?;
+                self.path(&node.path)?;
+            }
+        }
+
+        self.u8(kind_tag(node.kind))?;
+        self.u64(node.size)?;
+        self.u64(node.allocated_size)?;
+        self.u64(node.hard_link_size)?;
+        self.u64(node.file_count)?;
+        self.u64(node.dir_count)?;
+        self.u64(node.other_count)?;
+        self.time(node.times.newest_mtime)?;
+        self.time(node.times.oldest_mtime)?;
+        self.time(node.times.newest_atime)?;
+
+        let extensions: Vec<_> = node.types.extensions().collect();
+        self.u64(extensions.len() as u64)?;
+        for (extension, tally) in extensions {
+            self.str(extension)?;
+            self.tally(tally)?;
+        }
+        self.ids(&node.owners.users)?;
+        self.ids(&node.owners.groups)?;
+        // merged nodes no longer have tables to work these out from
+        for category in node.dominant.categories {
+            self.u8(category.map_or(0, |category| category_tag(category) + 1))?;
+        }
+        for user in node.dominant.users {
+            self.u64(user.map_or(0, |uid| uid as u64 + 1))?;
+        }
+
+        let mut flags = 0;
+        if node.incomplete {
+            flags |= FLAG_INCOMPLETE;
+        }
+        if node.mount_point {
+            flags |= FLAG_MOUNT_POINT;
+        }
+        if node.skipped {
+            flags |= FLAG_SKIPPED;
+        }
+        self.u8(flags)?;
+        self.u64(node.small_items)?;
+
+        self.u64(node.children.len() as u64)
+    }
+
+    fn result(&mut self, result: &ScanResult) -> io::Result<()> {
+        self.tree(&result.root)?;
+
+        self.u64(result.errors.len() as u64)?;
+        for error in &result.errors {
+            self.path(&error.path)?;
+            self.u8(error_tag(error.kind))?;
+        }
+        self.paths(&result.skipped_mounts)?;
+        self.paths(&result.excluded)?;
+
+        self.u64(result.largest_files.len() as u64)?;
+        for file in &result.largest_files {
+            self.path(&file.path)?;
+            self.u64(file.size)?;
+            self.u64(file.allocated_size)?;
+        }
+        self.paths(&result.empty_dirs)?;
+        self.paths(&result.empty_files)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    #[inline]
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| invalid("snapshot is truncated"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    #[inline]
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("number too large"))
+    }
+
+    #[inline]
+    fn usize(&mut self) -> io::Result<usize> {
+        usize::try_from(self.u64()?).map_err(|_| invalid("number too large"))
+    }
+
+    #[inline]
+    fn u32(&mut self) -> io::Result<u32> {
+        u32::try_from(self.u64()?).map_err(|_| invalid("number too large"))
+    }
+
+    // every element takes at least one byte, so this bounds what a corrupt length can allocate
+    #[inline]
+    fn len(&mut self) -> io::Result<usize> {
+        let len = self.usize()?;
+        if len > self.data.len() - self.pos {
+            return Err(invalid("snapshot is truncated"));
+        }
+        Ok(len)
+    }
+
+    fn str(&mut self) -> io::Result<String> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| invalid("invalid text"))
+    }
+
+    #[inline]
+    fn path(&mut self) -> io::Result<PathBuf> {
+        self.str().map(PathBuf::from)
+    }
+
+    fn paths(&mut self) -> io::Result<Vec<PathBuf>> {
+        let len = self.len()?;
+        (0..len).map(|_| self.path()).collect()
+    }
+
+    fn strings(&mut self) -> io::Result<Vec<String>> {
+        let len = self.len()?;
+        (0..len).map(|_| self.str()).collect()
+    }
+
+    fn time(&mut self) -> io::Result<Option<SystemTime>> {
+        let sign = self.u8()?;
+        if sign == 0 {
+            return Ok(None);
+        }
+        let seconds = self.u64()?;
+        let nanos = self.u32()?;
+        if nanos >= 1_000_000_000 {
+            return Err(invalid("invalid time"));
+        }
+        let duration = Duration::new(seconds, nanos);
+        let time = match sign {
+            1 => UNIX_EPOCH.checked_add(duration),
+            2 => UNIX_EPOCH.checked_sub(duration),
+            _ => None,
+        };
+        time.map(Some).ok_or_else(|| invalid("invalid time"))
+    }
+
+    fn tally(&mut self) -> io::Result<Tally> {
+        Ok(Tally {
+            size: self.u64()?,
+            allocated_size: self.u64()?,
+            count: self.u64()?,
+        })
+    }
+
+    fn ids(&mut self) -> io::Result<std::collections::HashMap<u32, Tally>> {
+        let len = self.len()?;
+        (0..len).map(|_| Ok((self.u32()?, self.tally()?))).collect()
+    }
+
+    fn options(&mut self) -> io::Result<ScanOptions> {
+        Ok(ScanOptions {
+            threads: self.usize()?,
+            detail_threshold: self.u64()?,
+            min_fraction: f64::from_bits(self.u64()?),
+            max_depth: match self.usize()? {
+                0 => None,
+                depth => Some(depth - 1),
+            },
+            file_threshold: self.u64()?,
+            largest_files: self.usize()?,
+            size_mode: size_mode_from_tag(self.u8()?)?,
+            one_file_system: self.u8()? != 0,
+            symlinks: symlink_from_tag(self.u8()?)?,
+            exclude: self.strings()?,
+            include: self.strings()?,
+            ignore_mode: ignore_from_tag(self.u8()?)?,
+        })
+    }
+
+    // the nodes written by `Writer::tree`, with an explicit stack of the open directories
+    fn tree(&mut self) -> io::Result<FolderNode> {
+        let mut open: Vec<(FolderNode, usize)> = Vec::new();
+        let mut current = self.node(None)?;
+
+        loop {
+            let (node, remaining) = current;
+            if remaining > 0 {
+                if open.len() >= MAX_NESTING {
+                    return Err(invalid("snapshot is nested too deeply"));
+                }
+                let child = self.node(Some(&node.path))?;
+                open.push((node, remaining - 1));
+                current = child;
+                continue;
+            }
+
+            match open.pop() {
+                Some((mut parent, remaining)) => {
+                    parent.children.push(node);
+                    current = (parent, remaining);
+                }
+                None => return Ok(node),
+            }
+        }
+    }
+
+    // a node and how many children follow it
+    fn node(&mut self, parent: Option<&Path>) -> io::Result<(FolderNode, usize)> {
+        let name = self.str()?;
+        let path = match (self.u8()?, parent) {
+            (PATH_PARENT, Some(parent)) => parent.to_path_buf(),
+            (PATH_CHILD, Some(parent)) => parent.join(&name),
+            (PATH_FULL, _) => self.path()?,
+            _ => return Err(invalid("invalid node path")),
+        };
+
+        let mut node = FolderNode::new(name, path, 0, 0);
+        node.kind = kind_from_tag(self.u8()?)?;
+        node.size = self.u64()?;
+        node.allocated_size = self.u64()?;
+        node.hard_link_size = self.u64()?;
+        node.file_count = self.u64()?;
+        node.dir_count = self.u64()?;
+        node.other_count = self.u64()?;
+        node.times = TimeRange {
+            newest_mtime: self.time()?,
+            oldest_mtime: self.time()?,
+            newest_atime: self.time()?,
+        };
+
+        let mut types = TypeTable::default();
+        for _ in 0..self.len()? {
+            let extension = self.str()?;
+            types.insert(extension, self.tally()?);
+        }
+        node.types = types;
+        node.owners = OwnerTable {
+            users: self.ids()?,
+            groups: self.ids()?,
+        };
+        for category in &mut node.dominant.categories {
+            *category = match self.u8()? {
+                0 => None,
+                tag => Some(category_from_tag(tag - 1)?),
+            };
+        }
+        for user in &mut node.dominant.users {
+            *user = match self.u64()? {
+                0 => None,
+                uid => Some(u32::try_from(uid - 1).map_err(|_| invalid("invalid user id"))?),
+            };
+        }
+
+        let flags = self.u8()?;
+        node.incomplete = flags & FLAG_INCOMPLETE != 0;
+        node.mount_point = flags & FLAG_MOUNT_POINT != 0;
+        node.skipped = flags & FLAG_SKIPPED != 0;
+        node.small_items = self.u64()?;
+
+        let len = self.len()?;
+        node.children = Vec::with_capacity(len);
+        Ok((node, len))
+    }
+
+    fn result(&mut self) -> io::Result<ScanResult> {
+        let root = self.tree()?;
+
+        let len = self.len()?;
+        let errors = (0..len)
+            .map(|_| {
+                Ok(ScanError {
+                    path: self.path()?,
+                    kind: error_from_tag(self.u8()?)?,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        let skipped_mounts = self.paths()?;
+        let excluded = self.paths()?;
+
+        let len = self.len()?;
+        let largest_files = (0..len)
+            .map(|_| {
+                Ok(LargeFile {
+                    path: self.path()?,
+                    size: self.u64()?,
+                    allocated_size: self.u64()?,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(ScanResult {
+            root,
+            errors,
+            skipped_mounts,
+            excluded,
+            largest_files,
+            empty_dirs: self.paths()?,
+            empty_files: self.paths()?,
+        })
+    }
+}
+
+// enums are stored by these tags, never reuse one for something else
+
+#[inline]
+fn category_tag(category: FileCategory) -> u8 {
+    match category {
+        FileCategory::Video => 0,
+        FileCategory::Audio => 1,
+        FileCategory::Image => 2,
+        FileCategory::Archive => 3,
+        FileCategory::DiskImage => 4,
+        FileCategory::Document => 5,
+        FileCategory::Code => 6,
+        FileCategory::Build => 7,
+        FileCategory::Data => 8,
+        FileCategory::Other => 9,
+    }
+}
+
+fn category_from_tag(tag: u8) -> io::Result<FileCategory> {
+    match tag {
+        0 => Ok(FileCategory::Video),
+        1 => Ok(FileCategory::Audio),
+        2 => Ok(FileCategory::Image),
+        3 => Ok(FileCategory::Archive),
+        4 => Ok(FileCategory::DiskImage),
+        5 => Ok(FileCategory::Document),
+        6 => Ok(FileCategory::Code),
+        7 => Ok(FileCategory::Build),
+        8 => Ok(FileCategory::Data),
+        9 => Ok(FileCategory::Other),
+        _ => Err(invalid("invalid file category")),
+    }
+}
+
+fn kind_tag(kind: NodeKind) -> u8 {
+    match kind {
+        NodeKind::Directory => 0,
+        NodeKind::File => 1,
+        NodeKind::Symlink => 2,
+        NodeKind::Other => 3,
+    }
+}
+
+fn kind_from_tag(tag: u8) -> io::Result<NodeKind> {
+    match tag {
+        0 => Ok(NodeKind::Directory),
+        1 => Ok(NodeKind::File),
+        2 => Ok(NodeKind::Symlink),
+        3 => Ok(NodeKind::Other),
+        _ => Err(invalid("invalid node kind")),
+    }
+}
+
+#[inline]
+fn size_mode_tag(mode: SizeMode) -> u8 {
+    match mode {
+        SizeMode::Apparent => 0,
+        SizeMode::Allocated => 1,
+        SizeMode::Items => 2,
+    }
+}
+
+fn size_mode_from_tag(tag: u8) -> io::Result<SizeMode> {
+    match tag {
+        0 => Ok(SizeMode::Apparent),
+        1 => Ok(SizeMode::Allocated),
+        2 => Ok(SizeMode::Items),
+        _ => Err(invalid("invalid size mode")),
+    }
+}
+
+#[inline]
+fn symlink_tag(policy: SymlinkPolicy) -> u8 {
+    match policy {
+        SymlinkPolicy::Never => 0,
+        SymlinkPolicy::WithinRoot => 1,
+        SymlinkPolicy::Always => 2,
+    }
+}
+
+fn symlink_from_tag(tag: u8) -> io::Result<SymlinkPolicy> {
+    match tag {
+        0 => Ok(SymlinkPolicy::Never),
+        1 => Ok(SymlinkPolicy::WithinRoot),
+        2 => Ok(SymlinkPolicy::Always),
+        _ => Err(invalid("invalid symlink policy")),
+    }
+}
+
+#[inline]
+fn ignore_tag(mode: IgnoreMode) -> u8 {
+    match mode {
+        IgnoreMode::Off => 0,
+        IgnoreMode::NotIgnored => 1,
+        IgnoreMode::IgnoredOnly => 2,
+    }
+}
+
+fn ignore_from_tag(tag: u8) -> io::Result<IgnoreMode> {
+    match tag {
+        0 => Ok(IgnoreMode::Off),
+        1 => Ok(IgnoreMode::NotIgnored),
+        2 => Ok(IgnoreMode::IgnoredOnly),
+        _ => Err(invalid("invalid ignore mode")),
+    }
+}
+
+#[inline]
+fn error_tag(kind: ScanErrorKind) -> u8 {
+    match kind {
+        ScanErrorKind::PermissionDenied => 0,
+        ScanErrorKind::Vanished => 1,
+        ScanErrorKind::Io => 2,
+    }
+}
+
+fn error_from_tag(tag: u8) -> io::Result<ScanErrorKind> {
+    match tag {
+        0 => Ok(ScanErrorKind::PermissionDenied),
+        1 => Ok(ScanErrorKind::Vanished),
+        2 => Ok(ScanErrorKind::Io),
+        _ => Err(invalid("invalid error kind")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Snapshot {
+        let mut root = FolderNode::new("data".to_string(), PathBuf::from("/data"), 300, 8192);
+        root.file_count = 3;
+        root.dir_count = 1;
+        root.times.newest_mtime = Some(UNIX_EPOCH + Duration::new(1_700_000_000, 123));
+        root.times.oldest_mtime = Some(UNIX_EPOCH - Duration::from_secs(60));
+
+        let mut sub = FolderNode::new("sub".to_string(), PathBuf::from("/data/sub"), 200, 4096);
+        sub.file_count = 2;
+        sub.incomplete = true;
+        let mut small = FolderNode::new(
+            "(2 smaller items)".to_string(),
+            root.path.clone(),
+            100,
+            4096,
+        );
+        small.small_items = 2;
+        root.children = vec![sub, small];
+
+        Snapshot {
+            scanned_at: UNIX_EPOCH + Duration::from_secs(1_700_000_100),
+            options: ScanOptions {
+                max_depth: Some(3),
+                exclude: vec!["*.tmp".to_string()],
+                ignore_mode: IgnoreMode::NotIgnored,
+                ..ScanOptions::default()
+            },
+            result: ScanResult {
+                root,
+                errors: vec![ScanError {
+                    path: PathBuf::from("/data/locked"),
+                    kind: ScanErrorKind::PermissionDenied,
+                }],
+                skipped_mounts: Vec::new(),
+                excluded: vec![PathBuf::from("/data/a.tmp")],
+                largest_files: vec![LargeFile {
+                    path: PathBuf::from("/data/sub/big"),
+                    size: 150,
+                    allocated_size: 4096,
+                }],
+                empty_dirs: vec![PathBuf::from("/data/empty")],
+                empty_files: Vec::new(),
+            },
+        }
+    }
+
+    fn encode(snapshot: &Snapshot) -> Vec<u8> {
+        let mut data = Vec::new();
+        snapshot.write_to(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn round_trip() {
+        let snapshot = sample();
+        let loaded = Snapshot::from_bytes(&encode(&snapshot)).unwrap();
+
+        assert_eq!(loaded.scanned_at, snapshot.scanned_at);
+        assert_eq!(loaded.options.max_depth, Some(3));
+        assert_eq!(loaded.options.exclude, snapshot.options.exclude);
+        assert_eq!(loaded.options.ignore_mode, IgnoreMode::NotIgnored);
+
+        let (root, original) = (&loaded.result.root, &snapshot.result.root);
+        assert_eq!(root.path, original.path);
+        assert_eq!(root.size, 300);
+        assert_eq!(root.times.newest_mtime, original.times.newest_mtime);
+        assert_eq!(root.times.oldest_mtime, original.times.oldest_mtime);
+        assert_eq!(root.children[0].path, PathBuf::from("/data/sub"));
+        assert!(root.children[0].incomplete);
+        assert_eq!(root.children[1].path, root.path);
+        assert_eq!(root.children[1].small_items, 2);
+
+        assert_eq!(
+            loaded.result.errors[0].kind,
+            ScanErrorKind::PermissionDenied
+        );
+        assert_eq!(loaded.result.largest_files, snapshot.result.largest_files);
+        assert_eq!(loaded.result.empty_dirs, snapshot.result.empty_dirs);
+    }
+
+    #[test]
+    fn truncated_or_extended_data_is_rejected() {
+        let data = encode(&sample());
+        for len in [
+            0,
+            4,
+            MAGIC.len(),
+            MAGIC.len() + 1,
+            data.len() / 2,
+            data.len() - 1,
+        ] {
+            assert!(Snapshot::from_bytes(&data[..len]).is_err(), "{} bytes", len);
+        }
+
+        let mut longer = data.clone();
+        longer.push(0);
+        assert!(Snapshot::from_bytes(&longer).is_err());
+    }
+
+    #[test]
+    fn foreign_files_and_versions_are_rejected() {
+        let mut data = encode(&sample());
+        data[0] = b'X';
+        assert!(Snapshot::from_bytes(&data).is_err());
+
+        let mut data = encode(&sample());
+        data[MAGIC.len()] = VERSION as u8 + 1;
+        let error = Snapshot::from_bytes(&data).unwrap_err();
+        assert!(error.to_string().contains("version"));
+    }
+
+    #[test]
+    fn invalid_times_are_errors() {
+        let read = |bytes: &[u8]| {
+            Reader {
+                data: bytes,
+                pos: 0,
+            }
+            .time()
+        };
+
+        assert_eq!(read(&[0]).unwrap(), None);
+        assert_eq!(
+            read(&[1, 5, 0]).unwrap(),
+            Some(UNIX_EPOCH + Duration::from_secs(5))
+        );
+        // nanos of 1_000_000_000 and more would carry over into the seconds
+        assert!(read(&[1, 0, 0x80, 0x94, 0xeb, 0xdc, 0x03]).is_err());
+        // past what `SystemTime` can hold
+        let mut huge = vec![1];
+        huge.extend([0xff; 9]);
+        huge.extend([0x01, 0]);
+        assert!(read(&huge).is_err());
+        assert!(read(&[3, 0, 0]).is_err());
+    }
+
+    fn nested(depth: usize) -> Snapshot {
+        let mut snapshot = sample();
+        let mut node = FolderNode::new("leaf".to_string(), PathBuf::from("/leaf"), 0, 0);
+        for _ in 0..depth {
+            let mut parent = FolderNode::new("d".to_string(), PathBuf::from("/d"), 0, 0);
+            parent.children.push(node);
+            node = parent;
+        }
+        snapshot.result.root = node;
+        snapshot
+    }
+
+    #[test]
+    fn deep_trees_are_saved_but_too_deep_files_refused() {
+        let loaded = Snapshot::from_bytes(&encode(&nested(MAX_NESTING))).unwrap();
+        let mut node = &loaded.result.root;
+        for _ in 0..MAX_NESTING {
+            node = &node.children[0];
+        }
+        assert_eq!(node.name, "leaf");
+
+        let error = Snapshot::from_bytes(&encode(&nested(MAX_NESTING + 1))).unwrap_err();
+        assert!(error.to_string().contains("nested too deeply"));
+    }
+}
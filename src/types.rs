@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::folder::SizeMode;
+use crate::theme::*;
+
+// rough kind of data a file holds, derived from its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FileCategory {
+    Video,
+    Audio,
+    Image,
+    Archive,
+    DiskImage,
+    Document,
+    Code,
+    Build, // object files, libraries and other compiler output
+    Data,
+    Other,
+}
+
+impl FileCategory {
+    pub fn from_extension(extension: &str) -> Self {
+        match extension {
+            // `ts` is far more often TypeScript than an MPEG transport stream
+            "mp4" | "mkv" | "avi" | "mov" | "webm" | "wmv" | "flv" | "m4v" | "mpg" | "mpeg"
+            | "m2ts" => Self::Video,
+            "mp3" | "flac" | "wav" | "ogg" | "opus" | "m4a" | "aac" | "wma" => Self::Audio,
+            "png" | "jpg" | "jpeg" | "gif" | "bmp" | "webp" | "tiff" | "tif" | "svg" | "ico"
+            | "heic" | "raw" | "cr2" | "nef" | "psd" => Self::Image,
+            "zip" | "tar" | "gz" | "tgz" | "xz" | "bz2" | "zst" | "7z" | "rar" | "lz4" | "deb"
+            | "rpm" | "jar" | "whl" | "crate" => Self::Archive,
+            "iso" | "img" | "dmg" | "vmdk" | "vdi" | "qcow2" | "vhd" | "vhdx" => Self::DiskImage,
+            "pdf" | "doc" | "docx" | "odt" | "xls" | "xlsx" | "ods" | "ppt" | "pptx" | "odp"
+            | "txt" | "md" | "rtf" | "epub" => Self::Document,
+            "rs" | "c" | "h" | "cc" | "cpp" | "hpp" | "py" | "js" | "jsx" | "ts" | "tsx"
+            | "java" | "kt" | "go" | "rb" | "php" | "cs" | "swift" | "sh" | "lua" | "html"
+            | "css" | "toml" | "yaml" | "yml" => Self::Code,
+            "o" | "obj" | "a" | "lib" | "so" | "dll" | "dylib" | "rlib" | "rmeta" | "pdb"
+            | "class" | "pyc" | "d" | "exe" | "wasm" | "pch" | "gch" | "incremental" => Self::Build,
+            "json" | "csv" | "xml" | "db" | "sqlite" | "sqlite3" | "parquet" | "log" | "bin"
+            | "dat" => Self::Data,
+            _ => Self::Other,
+        }
+    }
+
+    pub fn color(self) -> u32 {
+        match self {
+            Self::Video => CATPPUCCIN_RED,
+            Self::Audio => CATPPUCCIN_PINK,
+            Self::Image => CATPPUCCIN_MAUVE,
+            Self::Archive => CATPPUCCIN_PEACH,
+            Self::DiskImage => CATPPUCCIN_MAROON,
+            Self::Document => CATPPUCCIN_YELLOW,
+            Self::Code => CATPPUCCIN_GREEN,
+            Self::Build => CATPPUCCIN_BLUE,
+            Self::Data => CATPPUCCIN_TEAL,
+            Self::Other => CATPPUCCIN_OVERLAY1,
+        }
+    }
+}
+
+impl fmt::Display for FileCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Video => write!(f, "Video"),
+            Self::Audio => write!(f, "Audio"),
+            Self::Image => write!(f, "Images"),
+            Self::Archive => write!(f, "Archives"),
+            Self::DiskImage => write!(f, "Disk images"),
+            Self::Document => write!(f, "Documents"),
+            Self::Code => write!(f, "Code"),
+            Self::Build => write!(f, "Build artifacts"),
+            Self::Data => write!(f, "Data"),
+            Self::Other => write!(f, "Other"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tally {
+    pub size: u64,
+    pub allocated_size: u64,
+    pub count: u64,
+}
+
+impl Tally {
+    #[inline(always)]
+    pub fn size_in(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.size,
+            SizeMode::Allocated => self.allocated_size,
+            SizeMode::Items => self.count,
+        }
+    }
+
+    #[inline(always)]
+    pub fn merge(&mut self, other: &Tally) {
+        self.size += other.size;
+        self.allocated_size += other.allocated_size;
+        self.count += other.count;
+    }
+}
+
+// bytes and file counts per lowercase extension ("" for files without one)
+#[derive(Debug, Clone, Default)]
+pub struct TypeTable(HashMap<String, Tally>);
+
+impl TypeTable {
+    #[inline]
+    pub fn add_file(&mut self, path: &Path, metadata: &fs::Metadata, allocated_size: u64) {
+        self.add(path, metadata.len(), allocated_size);
+    }
+
+    pub fn add(&mut self, path: &Path, size: u64, allocated_size: u64) {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        self.0.entry(extension).or_default().merge(&Tally {
+            size,
+            allocated_size,
+            count: 1,
+        });
+    }
+
+    #[inline]
+    pub fn insert(&mut self, extension: String, tally: Tally) {
+        self.0.insert(extension, tally);
+    }
+
+    pub fn merge(&mut self, other: &TypeTable) {
+        for (extension, tally) in &other.0 {
+            match self.0.get_mut(extension) {
+                Some(existing) => existing.merge(tally),
+                None => {
+                    self.0.insert(extension.clone(), *tally);
+                }
+            }
+        }
+    }
+
+    pub fn extensions(&self) -> impl Iterator<Item = (&str, &Tally)> {
+        self.0
+            .iter()
+            .map(|(extension, tally)| (extension.as_str(), tally))
+    }
+
+    pub fn categories(&self) -> Vec<(FileCategory, Tally)> {
+        let mut categories: HashMap<FileCategory, Tally> = HashMap::new();
+        for (extension, tally) in &self.0 {
+            categories
+                .entry(FileCategory::from_extension(extension))
+                .or_default()
+                .merge(tally);
+        }
+        categories.into_iter().collect()
+    }
+
+    // the category taking up most of the space in every size mode, used for coloring
+    pub fn dominant_categories(&self) -> [Option<FileCategory>; 3] {
+        let categories = self.categories();
+        SizeMode::ALL.map(|mode| {
+            categories
+                .iter()
+                .filter(|(_, tally)| tally.size_in(mode) > 0)
+                .max_by_key(|(category, tally)| (tally.size_in(mode), std::cmp::Reverse(*category)))
+                .map(|(category, _)| *category)
+        })
+    }
+}
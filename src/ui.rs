@@ -1,6 +1,19 @@
 use fltk::{prelude::*, *};
 
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::duplicates::DuplicateGroup;
+use crate::filter::ScanFilter;
+use crate::folder::SizeMode;
+use crate::owners::{OwnerNames, OwnerTable};
+use crate::scan::{
+    LargeFile, MaxDepth, MinFraction, ScanError, ScanOptions, ScanProgress, ThreadCount,
+};
 use crate::theme::*;
+use crate::types::{FileCategory, Tally, TypeTable};
+use crate::utils::{format_size, is_still_empty, move_to_trash};
 
 #[inline]
 pub fn style_button(btn: &mut button::Button, color: u32) {
@@ -11,6 +24,16 @@ pub fn style_button(btn: &mut button::Button, color: u32) {
     btn.set_frame(enums::FrameType::RFlatBox);
 }
 
+#[inline]
+pub fn style_menu_button(btn: &mut menu::MenuButton) {
+    btn.set_color(enums::Color::from_u32(CATPPUCCIN_SURFACE1));
+    btn.set_selection_color(enums::Color::from_u32(CATPPUCCIN_SURFACE2));
+    btn.set_label_color(enums::Color::from_u32(CATPPUCCIN_TEXT));
+    btn.set_label_font(enums::Font::HelveticaBold);
+    btn.set_text_color(enums::Color::from_u32(CATPPUCCIN_TEXT));
+    btn.set_frame(enums::FrameType::RFlatBox);
+}
+
 pub fn update_progress_bar(bar: &mut frame::Frame, percentage: i32) {
     bar.draw(move |b| {
         let total_width = b.w();
@@ -41,3 +64,576 @@ pub fn update_progress_bar(bar: &mut frame::Frame, percentage: i32) {
         parent.redraw();
     }
 }
+
+// status line shown while a scan is running
+pub fn format_progress(progress: &ScanProgress) -> String {
+    if progress.percentage >= 100 {
+        return progress.message.clone();
+    }
+
+    let eta = match progress.eta {
+        Some(eta) => format!("{}s", eta.as_secs()),
+        None => "unknown".to_string(),
+    };
+
+    format!(
+        "{} - {} entries at {:.0}/s, {} so far, ETA {}",
+        progress.message,
+        progress.entries,
+        progress.entries_per_second,
+        format_size(progress.bytes),
+        eta
+    )
+}
+
+#[inline]
+pub fn style_browser<B: BrowserExt>(browser: &mut B) {
+    browser.set_color(enums::Color::from_u32(CATPPUCCIN_SURFACE0));
+    browser.set_selection_color(enums::Color::from_u32(CATPPUCCIN_SURFACE2));
+    browser.set_frame(enums::FrameType::FlatBox);
+    browser.set_text_size(12);
+}
+
+#[inline]
+pub fn style_choice(choice: &mut menu::Choice) {
+    choice.set_color(enums::Color::from_u32(CATPPUCCIN_SURFACE1));
+    choice.set_text_color(enums::Color::from_u32(CATPPUCCIN_TEXT));
+    choice.set_label_color(enums::Color::from_u32(CATPPUCCIN_TEXT));
+    choice.set_frame(enums::FrameType::FlatBox);
+}
+
+// browser line with every column drawn in the text color (format codes disabled)
+pub fn browser_row(columns: &[&str]) -> String {
+    let color = enums::Color::from_u32(CATPPUCCIN_TEXT).bits();
+    columns
+        .iter()
+        .map(|column| format!("@C{}@.{}", color, column))
+        .collect::<Vec<_>>()
+        .join("\t")
+}
+
+pub fn show_scan_summary(errors: &[ScanError], excluded: &[PathBuf]) {
+    let mut wind = window::Window::default()
+        .with_size(700, 400)
+        .with_label("Scan summary");
+    wind.set_color(enums::Color::from_u32(CATPPUCCIN_BASE));
+    wind.make_resizable(true);
+
+    let mut list = browser::HoldBrowser::new(10, 10, 680, 380, "");
+    style_browser(&mut list);
+    list.set_column_widths(&[140]);
+
+    for error in errors {
+        let kind = error.kind.to_string();
+        let path = error.path.display().to_string();
+        list.add(&browser_row(&[&kind, &path]));
+    }
+
+    for path in excluded {
+        let path = path.display().to_string();
+        list.add(&browser_row(&["Excluded", &path]));
+    }
+
+    wind.end();
+    wind.show();
+}
+
+// one pattern per line, blank lines are ignored
+#[inline]
+fn pattern_lines(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[inline]
+fn style_pattern_input(input: &mut input::MultilineInput, patterns: &[String]) {
+    input.set_value(&patterns.join("\n"));
+    input.set_color(enums::Color::from_u32(CATPPUCCIN_SURFACE0));
+    input.set_text_color(enums::Color::from_u32(CATPPUCCIN_TEXT));
+    input.set_cursor_color(enums::Color::from_u32(CATPPUCCIN_TEXT));
+    input.set_label_color(enums::Color::from_u32(CATPPUCCIN_TEXT));
+    input.set_frame(enums::FrameType::FlatBox);
+    input.set_align(enums::Align::TopLeft);
+}
+
+// edit the exclude/include patterns used by the next scan
+pub fn show_filter_dialog<F>(exclude: &[String], include: &[String], mut on_apply: F)
+where
+    F: FnMut(Vec<String>, Vec<String>) + 'static,
+{
+    let mut wind = window::Window::default()
+        .with_size(500, 400)
+        .with_label("Scan filters");
+    wind.set_color(enums::Color::from_u32(CATPPUCCIN_BASE));
+
+    let mut exclude_input =
+        input::MultilineInput::new(10, 30, 480, 140, "Exclude (one glob per line)");
+    style_pattern_input(&mut exclude_input, exclude);
+
+    let mut include_input =
+        input::MultilineInput::new(10, 200, 480, 140, "Only include files (empty for all)");
+    style_pattern_input(&mut include_input, include);
+
+    let mut cancel_button = button::Button::new(250, 350, 115, 40, "Cancel");
+    style_button(&mut cancel_button, CATPPUCCIN_RED);
+
+    let mut apply_button = button::Button::new(375, 350, 115, 40, "Apply");
+    style_button(&mut apply_button, CATPPUCCIN_GREEN);
+
+    wind.end();
+    wind.show();
+
+    {
+        let mut wind = wind.clone();
+        cancel_button.set_callback(move |_| wind.hide());
+    }
+
+    apply_button.set_callback(move |_| {
+        let exclude = pattern_lines(&exclude_input.value());
+        let include = pattern_lines(&include_input.value());
+
+        // keep the dialog open until every pattern is valid
+        if let Err(e) = ScanFilter::new(&exclude, &include) {
+            dialog::alert_default(&e);
+            return;
+        }
+
+        on_apply(exclude, include);
+        wind.hide();
+    });
+}
+
+#[inline]
+fn style_input<I: InputExt>(input: &mut I, value: &str) {
+    input.set_value(value);
+    input.set_color(enums::Color::from_u32(CATPPUCCIN_SURFACE0));
+    input.set_text_color(enums::Color::from_u32(CATPPUCCIN_TEXT));
+    input.set_cursor_color(enums::Color::from_u32(CATPPUCCIN_TEXT));
+    input.set_label_color(enums::Color::from_u32(CATPPUCCIN_TEXT));
+    input.set_frame(enums::FrameType::FlatBox);
+}
+
+// edit the thread count and detail limits used by the next scan
+pub fn show_scan_settings<F>(options: &ScanOptions, mut on_apply: F)
+where
+    F: FnMut(ScanOptions) + 'static,
+{
+    let mut wind = window::Window::default()
+        .with_size(420, 270)
+        .with_label("Scan settings");
+    wind.set_color(enums::Color::from_u32(CATPPUCCIN_BASE));
+
+    let mut threads_input = input::IntInput::new(250, 10, 160, 30, "Scanner threads");
+    style_input(&mut threads_input, &options.threads.to_string());
+
+    let mut threshold_input = input::IntInput::new(250, 50, 160, 30, "Min. folder size (bytes)");
+    style_input(&mut threshold_input, &options.detail_threshold.to_string());
+
+    let mut fraction_input = input::FloatInput::new(250, 90, 160, 30, "Min. fraction of total");
+    style_input(&mut fraction_input, &options.min_fraction.to_string());
+
+    let mut depth_input =
+        input::IntInput::new(250, 130, 160, 30, "Max. depth (empty or 0 for all)");
+    let depth = options.max_depth.map(|depth| depth.to_string());
+    style_input(&mut depth_input, depth.as_deref().unwrap_or(""));
+
+    let mut file_input = input::IntInput::new(250, 170, 160, 30, "Min. file size (bytes)");
+    style_input(&mut file_input, &options.file_threshold.to_string());
+
+    let mut cancel_button = button::Button::new(170, 220, 115, 40, "Cancel");
+    style_button(&mut cancel_button, CATPPUCCIN_RED);
+
+    let mut apply_button = button::Button::new(295, 220, 115, 40, "Apply");
+    style_button(&mut apply_button, CATPPUCCIN_GREEN);
+
+    wind.end();
+    wind.show();
+
+    {
+        let mut wind = wind.clone();
+        cancel_button.set_callback(move |_| wind.hide());
+    }
+
+    let mut options = options.clone();
+    apply_button.set_callback(move |_| {
+        let parsed = (|| {
+            options.threads = threads_input.value().trim().parse::<ThreadCount>().ok()?.0;
+            options.detail_threshold = threshold_input.value().trim().parse().ok()?;
+            options.min_fraction = fraction_input.value().trim().parse::<MinFraction>().ok()?.0;
+            options.max_depth = match depth_input.value().trim() {
+                "" => None,
+                depth => depth.parse::<MaxDepth>().ok()?.0,
+            };
+            options.file_threshold = file_input.value().trim().parse().ok()?;
+            Some(())
+        })();
+
+        if parsed.is_none() {
+            dialog::alert_default("Please enter valid numbers for every setting");
+            return;
+        }
+
+        on_apply(options.clone());
+        wind.hide();
+    });
+}
+
+// one line of a usage breakdown
+struct UsageRow {
+    name: String,
+    detail: String, // category of an extension, numeric id of an owner
+    tally: Tally,
+}
+
+fn fill_usage_list(
+    list: &mut browser::HoldBrowser,
+    rows: &mut [UsageRow],
+    sort: i32,
+    mode: SizeMode,
+) {
+    match sort {
+        1 => rows.sort_by_key(|row| std::cmp::Reverse(row.tally.count)),
+        2 => rows.sort_by(|a, b| a.name.cmp(&b.name)),
+        _ => rows.sort_by_key(|row| std::cmp::Reverse(row.tally.size_in(mode.bytes()))),
+    }
+
+    let total: u64 = rows.iter().map(|row| row.tally.size_in(mode)).sum();
+
+    list.clear();
+    for row in rows.iter() {
+        let share = row.tally.size_in(mode) as f64 * 100.0 / total.max(1) as f64;
+        list.add(&browser_row(&[
+            &row.name,
+            &row.detail,
+            &format_size(row.tally.size_in(mode.bytes())),
+            &format!("{} files", row.tally.count),
+            &format!("{:.1}%", share),
+        ]));
+    }
+}
+
+// sortable table with two ways of grouping the same usage, e.g. "Extension|Category"
+fn show_usage_breakdown(title: &str, groups: &str, rows: [Vec<UsageRow>; 2], mode: SizeMode) {
+    let mut wind = window::Window::default()
+        .with_size(600, 440)
+        .with_label(title);
+    wind.set_color(enums::Color::from_u32(CATPPUCCIN_BASE));
+    wind.make_resizable(true);
+
+    let mut group_choice = menu::Choice::new(80, 10, 160, 30, "Group by");
+    group_choice.add_choice(groups);
+    group_choice.set_value(0);
+
+    let mut sort_choice = menu::Choice::new(320, 10, 160, 30, "Sort by");
+    sort_choice.add_choice("Size|Files|Name");
+    sort_choice.set_value(0);
+
+    style_choice(&mut group_choice);
+    style_choice(&mut sort_choice);
+
+    let mut list = browser::HoldBrowser::new(10, 50, 580, 380, "");
+    style_browser(&mut list);
+    list.set_column_widths(&[140, 140, 100, 100]);
+
+    wind.end();
+    wind.show();
+
+    // both groupings are prepared once, changing a choice only sorts and refills
+    let mut rows = rows;
+    let refill = Rc::new(RefCell::new({
+        let group_choice = group_choice.clone();
+        let sort_choice = sort_choice.clone();
+        move || {
+            let group = group_choice.value().clamp(0, 1) as usize;
+            fill_usage_list(&mut list, &mut rows[group], sort_choice.value(), mode);
+        }
+    }));
+
+    for choice in [&mut group_choice, &mut sort_choice] {
+        let refill = Rc::clone(&refill);
+        choice.set_callback(move |_| (*refill.borrow_mut())());
+    }
+    (*refill.borrow_mut())();
+}
+
+// usage per extension or category of a node
+pub fn show_type_breakdown(name: &str, types: &TypeTable, mode: SizeMode) {
+    let extensions = types
+        .extensions()
+        .map(|(extension, tally)| UsageRow {
+            name: match extension {
+                "" => "(none)".to_string(),
+                extension => format!(".{}", extension),
+            },
+            detail: FileCategory::from_extension(extension).to_string(),
+            tally: *tally,
+        })
+        .collect();
+    let categories = types
+        .categories()
+        .into_iter()
+        .map(|(category, tally)| UsageRow {
+            name: category.to_string(),
+            detail: String::new(),
+            tally,
+        })
+        .collect();
+
+    show_usage_breakdown(
+        &format!("File types in {}", name),
+        "Extension|Category",
+        [extensions, categories],
+        mode,
+    );
+}
+
+// usage per owning user or group of a node
+pub fn show_owner_breakdown(name: &str, owners: &OwnerTable, mode: SizeMode) {
+    if owners.users.is_empty() {
+        dialog::message_default("No owner information, it is only collected on Unix.");
+        return;
+    }
+
+    let names = OwnerNames::load();
+    let users = owners
+        .users
+        .iter()
+        .map(|(uid, tally)| UsageRow {
+            name: names.user(*uid),
+            detail: format!("uid {}", uid),
+            tally: *tally,
+        })
+        .collect();
+    let groups = owners
+        .groups
+        .iter()
+        .map(|(gid, tally)| UsageRow {
+            name: names.group(*gid),
+            detail: format!("gid {}", gid),
+            tally: *tally,
+        })
+        .collect();
+
+    show_usage_breakdown(
+        &format!("Owners in {}", name),
+        "User|Group",
+        [users, groups],
+        mode,
+    );
+}
+
+// sorts `files` in place so browser line `n` shows `files[n - 1]`
+pub fn fill_largest_files(
+    list: &mut browser::HoldBrowser,
+    files: &mut [LargeFile],
+    sort: i32,
+    mode: SizeMode,
+) {
+    match sort {
+        1 => files.sort_by_cached_key(|file| file.path.file_name().map(|name| name.to_os_string())),
+        2 => files.sort_by(|a, b| a.path.cmp(&b.path)),
+        _ => files.sort_by_key(|file| std::cmp::Reverse(file.size_in(mode.bytes()))),
+    }
+
+    list.clear();
+    for file in files.iter() {
+        let name = file
+            .path
+            .file_name()
+            .unwrap_or(file.path.as_os_str())
+            .to_string_lossy();
+        list.add(&browser_row(&[
+            &format_size(file.size_in(mode.bytes())),
+            &name,
+        ]));
+    }
+}
+
+// duplicate groups on top, the copies of the selected group below,
+// `on_select` gets the paths to highlight in the treemap
+pub fn show_duplicates<F>(groups: Vec<DuplicateGroup>, on_select: F)
+where
+    F: FnMut(&[PathBuf]) + 'static,
+{
+    let wasted: u64 = groups.iter().map(DuplicateGroup::wasted).sum();
+    let mut wind = window::Window::default()
+        .with_size(700, 500)
+        .with_label(&format!(
+            "Duplicates: {} groups, {} wasted",
+            groups.len(),
+            format_size(wasted)
+        ));
+    wind.set_color(enums::Color::from_u32(CATPPUCCIN_BASE));
+    wind.make_resizable(true);
+
+    let mut group_list = browser::HoldBrowser::new(10, 10, 680, 280, "");
+    style_browser(&mut group_list);
+    group_list.set_column_widths(&[100, 80, 100]);
+
+    let mut copy_list = browser::HoldBrowser::new(10, 300, 680, 190, "");
+    style_browser(&mut copy_list);
+
+    wind.end();
+    wind.show();
+
+    if groups.is_empty() {
+        group_list.add(&browser_row(&["No duplicate files found"]));
+        return;
+    }
+
+    for group in &groups {
+        let name = group.paths[0]
+            .file_name()
+            .unwrap_or(group.paths[0].as_os_str())
+            .to_string_lossy();
+        group_list.add(&browser_row(&[
+            &format!("{} wasted", format_size(group.wasted())),
+            &format!("{} copies", group.paths.len()),
+            &format_size(group.size),
+            &name,
+        ]));
+    }
+
+    let groups = Rc::new(groups);
+    let on_select = Rc::new(RefCell::new(on_select));
+
+    // browser lines start at 1, 0 means nothing is selected
+    {
+        let groups = Rc::clone(&groups);
+        let on_select = Rc::clone(&on_select);
+        let mut copy_list = copy_list.clone();
+        group_list.set_callback(move |list| {
+            let Some(group) = usize::try_from(list.value() - 1)
+                .ok()
+                .and_then(|index| groups.get(index))
+            else {
+                return;
+            };
+
+            copy_list.clear();
+            for path in &group.paths {
+                copy_list.add(&browser_row(&[&path.display().to_string()]));
+            }
+            (*on_select.borrow_mut())(&group.paths);
+        });
+    }
+
+    copy_list.set_callback(move |list| {
+        let Some(group) = usize::try_from(group_list.value() - 1)
+            .ok()
+            .and_then(|index| groups.get(index))
+        else {
+            return;
+        };
+
+        match usize::try_from(list.value() - 1)
+            .ok()
+            .and_then(|index| group.paths.get(index))
+        {
+            Some(path) => (*on_select.borrow_mut())(std::slice::from_ref(path)),
+            None => (*on_select.borrow_mut())(&group.paths),
+        }
+    });
+}
+
+// empty folders and zero-byte files of the last scan, selected lines can be trashed
+// `can_trash` only for a live scan, the entries of an opened or imported one may be long gone
+pub fn show_empty_report(empty_dirs: &[PathBuf], empty_files: &[PathBuf], can_trash: bool) {
+    let mut wind = window::Window::default()
+        .with_size(700, 450)
+        .with_label(&format!(
+            "{} empty folders, {} empty files",
+            empty_dirs.len(),
+            empty_files.len()
+        ));
+    wind.set_color(enums::Color::from_u32(CATPPUCCIN_BASE));
+    wind.make_resizable(true);
+
+    let mut list = browser::MultiBrowser::new(10, 10, 680, 380, "");
+    style_browser(&mut list);
+    list.set_column_widths(&[100]);
+
+    let mut trash_button = button::Button::new(510, 400, 180, 40, "Move to trash");
+    style_button(&mut trash_button, CATPPUCCIN_RED);
+
+    wind.end();
+    wind.show();
+
+    // (path, is a folder, still there) for every line of the list
+    let mut entries: Vec<(PathBuf, bool, bool)> = Vec::new();
+    for (kind, paths, is_dir) in [
+        ("Empty folder", empty_dirs, true),
+        ("Empty file", empty_files, false),
+    ] {
+        for path in paths {
+            list.add(&browser_row(&[kind, &path.display().to_string()]));
+            entries.push((path.clone(), is_dir, true));
+        }
+    }
+
+    if !can_trash {
+        trash_button.set_tooltip("Only the results of a scan can be moved to the trash");
+    }
+    if entries.is_empty() || !can_trash {
+        trash_button.deactivate();
+        return;
+    }
+
+    trash_button.set_callback(move |_| {
+        let targets: Vec<usize> = (1..=entries.len() as i32)
+            .filter(|line| list.selected(*line))
+            .map(|line| line as usize - 1)
+            .filter(|index| entries[*index].2)
+            .collect();
+
+        if targets.is_empty() {
+            return;
+        }
+
+        let question = format!("Move {} items to the trash?", targets.len());
+        if dialog::choice2_default(&question, "Cancel", "Move to trash", "") != Some(1) {
+            return;
+        }
+
+        let mut failed = Vec::new();
+        let mut changed = Vec::new();
+        for index in targets {
+            let (path, is_dir, present) = &mut entries[index];
+
+            // something may have been written there since the scan
+            if !is_still_empty(path, *is_dir) {
+                changed.push(path.display().to_string());
+                continue;
+            }
+
+            match move_to_trash(path) {
+                Ok(()) => {
+                    *present = false;
+                    let line = index as i32 + 1;
+                    let text = list.text(line).unwrap_or_default();
+                    list.set_text(line, &format!("{} (trashed)", text));
+                    list.deselect(line);
+                }
+                Err(e) => failed.push(format!("{}: {}", path.display(), e)),
+            }
+        }
+
+        if !changed.is_empty() {
+            dialog::alert_default(&format!(
+                "{} items are no longer empty and were left alone:\n{}",
+                changed.len(),
+                changed.join("\n")
+            ));
+        }
+        if !failed.is_empty() {
+            dialog::alert_default(&format!(
+                "{} items could not be moved to the trash:\n{}",
+                failed.len(),
+                failed.join("\n")
+            ));
+        }
+    });
+}
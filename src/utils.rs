@@ -1,3 +1,7 @@
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
 
 #[inline(always)]
@@ -16,3 +20,188 @@ pub fn format_size(bytes: u64) -> String {
         format!("{:.2} {}", size, UNITS[unit_index])
     }
 }
+
+// show `path` selected in the platform file manager (or at least its folder)
+pub fn reveal_in_file_manager(path: &Path) {
+    #[cfg(target_os = "windows")]
+    let result = std::process::Command::new("explorer")
+        .arg(format!("/select,{}", path.display()))
+        .spawn()
+        .map(|_| ());
+
+    #[cfg(target_os = "macos")]
+    let result = std::process::Command::new("open")
+        .arg("-R")
+        .arg(path)
+        .spawn()
+        .map(|_| ());
+
+    // no common way to select a file here, open its folder instead
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let result = open::that(path.parent().unwrap_or(path));
+
+    if result.is_err() {
+        eprintln!("Failed to reveal path: {}", path.display());
+    }
+}
+
+// whether an entry reported as empty still is: a zero-byte file or a folder
+// with nothing but empty folders below it (links are never followed)
+pub fn is_still_empty(path: &Path, is_dir: bool) -> bool {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return false;
+    };
+    if !is_dir {
+        return metadata.is_file() && metadata.len() == 0;
+    }
+    if !metadata.is_dir() {
+        return false;
+    }
+
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return false;
+    };
+    entries
+        .into_iter()
+        .all(|entry| entry.is_ok_and(|entry| is_still_empty(&entry.path(), true)))
+}
+
+// move `path` to the trash (recycle bin) instead of deleting it for good
+#[cfg(target_os = "windows")]
+pub fn move_to_trash(path: &Path) -> io::Result<()> {
+    let path = path.display().to_string().replace('\'', "''");
+    let script = format!(
+        "Add-Type -AssemblyName Microsoft.VisualBasic; \
+         if (Test-Path -LiteralPath '{0}' -PathType Container) {{ \
+         [Microsoft.VisualBasic.FileIO.FileSystem]::DeleteDirectory('{0}', 'OnlyErrorDialogs', 'SendToRecycleBin') \
+         }} else {{ \
+         [Microsoft.VisualBasic.FileIO.FileSystem]::DeleteFile('{0}', 'OnlyErrorDialogs', 'SendToRecycleBin') }}",
+        path
+    );
+    run_command(std::process::Command::new("powershell").args(["-NoProfile", "-Command", &script]))
+}
+
+#[cfg(target_os = "macos")]
+pub fn move_to_trash(path: &Path) -> io::Result<()> {
+    let path = std::path::absolute(path)?
+        .display()
+        .to_string()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"");
+    let script = format!(
+        "tell application \"Finder\" to delete POSIX file \"{}\"",
+        path
+    );
+    run_command(std::process::Command::new("osascript").args(["-e", &script]))
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+fn run_command(command: &mut std::process::Command) -> io::Result<()> {
+    let output = command.output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+// the home trash of the freedesktop.org trash spec, other file systems can't be renamed into it
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub fn move_to_trash(path: &Path) -> io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+    use std::{env, fs};
+
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .ok_or_else(|| io::Error::other("no home directory"))?;
+    let files_dir = data_home.join("Trash").join("files");
+    let info_dir = data_home.join("Trash").join("info");
+    fs::create_dir_all(&files_dir)?;
+    fs::create_dir_all(&info_dir)?;
+
+    let path = std::path::absolute(path)?;
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::other("can't trash a root directory"))?
+        .to_string_lossy()
+        .to_string();
+
+    // the original path is percent encoded like an URL path
+    let mut encoded = String::new();
+    for byte in path.as_os_str().as_bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(*byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    let now = SystemTime::now();
+    let seconds = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) % 86400;
+    let deletion_date = format!(
+        "{}T{:02}:{:02}:{:02}",
+        format_date(now),
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+
+    // creating the .trashinfo file first claims the name in the trash
+    let mut counter = 0;
+    loop {
+        let trashed = match counter {
+            0 => name.clone(),
+            n => format!("{}.{}", name, n),
+        };
+        counter += 1;
+
+        let info_path = info_dir.join(format!("{}.trashinfo", trashed));
+        let mut info = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(info) => info,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        write!(
+            info,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encoded, deletion_date
+        )?;
+
+        if let Err(e) = fs::rename(&path, files_dir.join(&trashed)) {
+            fs::remove_file(&info_path).ok();
+            return Err(e);
+        }
+        return Ok(());
+    }
+}
+
+// `YYYY-MM-DD` (UTC) without pulling in a date crate
+pub fn format_date(time: SystemTime) -> String {
+    let days = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => (duration.as_secs() / 86400) as i64,
+        Err(e) => -(e.duration().as_secs().div_ceil(86400) as i64),
+    };
+
+    // civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
@@ -2,12 +2,32 @@ use fltk::draw::*;
 use fltk::enums::*;
 use fltk::{prelude::*, *};
 use std::cell::RefCell;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::{Duration, SystemTime};
 
-use crate::folder::FolderNode;
+use crate::folder::{FolderNode, NodeKind, SizeMode, TimeRange};
+use crate::owners::{OwnerNames, owner_color};
 use crate::theme::*;
-use crate::utils::format_size;
+use crate::ui::{show_owner_breakdown, show_type_breakdown};
+use crate::utils::{format_date, format_size, reveal_in_file_manager};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+// what the fill color of a rectangle tells
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorMode {
+    #[default]
+    Depth,
+    Age,      // how long ago the newest file was modified
+    FileType, // category taking up most of the space
+    Owner,    // user owning most of the space
+}
+
+const MENU_OPEN: &str = "Open";
+const MENU_REVEAL: &str = "Reveal in file manager";
+const MENU_TYPES: &str = "File types...";
+const MENU_OWNERS: &str = "Owners...";
 
 #[derive(Clone, Debug)]
 struct TreemapRect {
@@ -17,7 +37,20 @@ struct TreemapRect {
     height: i32,
     name: String,
     path: PathBuf,
+    kind: NodeKind,
     size: u64,
+    apparent_size: u64,
+    allocated_size: u64,
+    hard_link_size: u64,
+    file_count: u64,
+    dir_count: u64,
+    other_count: u64,
+    times: TimeRange,
+    owner: Option<u32>, // uid owning most of the space
+    incomplete: bool,
+    mount_point: bool,
+    skipped: bool,
+    small_items: u64,
     depth: u32,
     color: Color,
 }
@@ -34,7 +67,12 @@ struct TreemapArea {
 struct TreemapData {
     rects: Vec<TreemapRect>,
     hovered_rect: Option<usize>,
+    selected_paths: Vec<PathBuf>, // highlighted from outside, e.g. the largest files list
     root_node: Option<FolderNode>,
+    size_mode: SizeMode,
+    color_mode: ColorMode,
+    owner_names: OwnerNames,
+    context_menu: menu::MenuItem,
 }
 
 pub struct TreemapWidget {
@@ -51,7 +89,12 @@ impl TreemapWidget {
         let data = Rc::new(RefCell::new(TreemapData {
             rects: Vec::new(),
             hovered_rect: None,
+            selected_paths: Vec::new(),
             root_node: None,
+            size_mode: SizeMode::default(),
+            color_mode: ColorMode::default(),
+            owner_names: OwnerNames::load(),
+            context_menu: menu::MenuItem::new(&[MENU_OPEN, MENU_REVEAL, MENU_TYPES, MENU_OWNERS]),
         }));
 
         let data_draw = data.clone();
@@ -80,7 +123,7 @@ impl TreemapWidget {
 
         // sort rectangles by depth
         let mut sorted_rects: Vec<(usize, &TreemapRect)> = data.rects.iter().enumerate().collect();
-        sorted_rects.sort_by(|a, b| a.1.depth.cmp(&b.1.depth));
+        sorted_rects.sort_by_key(|(_, rect)| rect.depth);
 
         // get all rectangles that should be highlighted
         let highlighted_rects = Self::get_highlighted_rects(&data.rects, data.hovered_rect);
@@ -90,12 +133,22 @@ impl TreemapWidget {
             Self::draw_rect(rect, highlighted_rects.contains(&i));
         }
 
-        // draw tooltip for hovered rectangle
-        if let Some(i) = data.hovered_rect {
-            if let Some(rect) = data.rects.get(i) {
-                Self::draw_tooltip(w, rect);
+        // outline the selected rectangles on top of everything
+        set_draw_color(Color::from_hex(CATPPUCCIN_TEXT));
+        set_line_style(LineStyle::Solid, 3);
+        for path in &data.selected_paths {
+            if let Some(rect) = Self::get_selected_rect(&data.rects, path) {
+                draw_rect(rect.x + 1, rect.y + 1, rect.width - 2, rect.height - 2);
             }
         }
+        set_line_style(LineStyle::Solid, 0);
+
+        // draw tooltip for hovered rectangle
+        if let Some(i) = data.hovered_rect
+            && let Some(rect) = data.rects.get(i)
+        {
+            Self::draw_tooltip(w, rect, data.size_mode, &data.owner_names);
+        }
     }
 
     fn draw_rect(rect: &TreemapRect, is_hovered: bool) {
@@ -115,9 +168,34 @@ impl TreemapWidget {
         // fill the rectangle
         draw_rect_fill(rect.x, rect.y, rect.width, rect.height, color);
 
+        // hatch merged small items so they don't look like a real folder
+        if rect.small_items > 0 {
+            set_draw_color(Color::from_hex(CATPPUCCIN_SURFACE2));
+            push_clip(rect.x, rect.y, rect.width, rect.height);
+            let mut offset = 0;
+            while offset < rect.width + rect.height {
+                draw_line(
+                    rect.x + offset,
+                    rect.y,
+                    rect.x + offset - rect.height,
+                    rect.y + rect.height,
+                );
+                offset += 8;
+            }
+            pop_clip();
+        }
+
         // draw a border
         draw_rect_with_color(rect.x, rect.y, rect.width, rect.height, border_color);
 
+        // outline other file systems so they stand out from regular folders
+        if rect.mount_point {
+            set_draw_color(Color::from_hex(CATPPUCCIN_RED));
+            set_line_style(LineStyle::Dash, 2);
+            draw_rect(rect.x + 1, rect.y + 1, rect.width - 2, rect.height - 2);
+            set_line_style(LineStyle::Solid, 0);
+        }
+
         // draw text if rectangle is large enough
         if rect.width > 50 && rect.height > 25 {
             if is_hovered {
@@ -154,16 +232,16 @@ impl TreemapWidget {
     ) -> std::collections::HashSet<usize> {
         let mut highlighted = std::collections::HashSet::new();
 
-        if let Some(hovered_idx) = hovered_rect {
-            if let Some(hovered) = rects.get(hovered_idx) {
-                // add the hovered rectangle itself
-                highlighted.insert(hovered_idx);
+        if let Some(hovered_idx) = hovered_rect
+            && let Some(hovered) = rects.get(hovered_idx)
+        {
+            // add the hovered rectangle itself
+            highlighted.insert(hovered_idx);
 
-                // find all children
-                for (i, rect) in rects.iter().enumerate() {
-                    if rect.depth > hovered.depth && Self::is_rect_inside(rect, hovered) {
-                        highlighted.insert(i);
-                    }
+            // find all children
+            for (i, rect) in rects.iter().enumerate() {
+                if rect.depth > hovered.depth && Self::is_rect_inside(rect, hovered) {
+                    highlighted.insert(i);
                 }
             }
         }
@@ -171,6 +249,20 @@ impl TreemapWidget {
         highlighted
     }
 
+    // the deepest rectangle containing `path`, real folders win over merged small items
+    fn get_selected_rect<'a>(rects: &'a [TreemapRect], path: &Path) -> Option<&'a TreemapRect> {
+        rects
+            .iter()
+            .filter(|rect| path.starts_with(&rect.path))
+            .max_by_key(|rect| {
+                (
+                    rect.path.as_os_str().len(),
+                    rect.small_items == 0,
+                    rect.depth,
+                )
+            })
+    }
+
     #[inline(always)]
     fn is_rect_inside(inner: &TreemapRect, outer: &TreemapRect) -> bool {
         inner.x >= outer.x
@@ -179,14 +271,78 @@ impl TreemapWidget {
             && inner.y + inner.height <= outer.y + outer.height
     }
 
-    fn draw_tooltip(w: &widget::Widget, rect: &TreemapRect) {
-        let size_formatted = format_size(rect.size);
-        let tooltip_text = format!(
-            "{}\nPath: {}\nSize: {}",
+    fn draw_tooltip(
+        w: &widget::Widget,
+        rect: &TreemapRect,
+        size_mode: SizeMode,
+        owner_names: &OwnerNames,
+    ) {
+        // the layout size comes first, item counts aren't a byte size
+        let (size, other_size) = match size_mode {
+            SizeMode::Allocated => (
+                rect.allocated_size,
+                format!("Apparent: {}", format_size(rect.apparent_size)),
+            ),
+            _ => (
+                rect.apparent_size,
+                format!("On disk: {}", format_size(rect.allocated_size)),
+            ),
+        };
+        let mut size_formatted = format_size(size);
+        if rect.incomplete {
+            size_formatted.push_str(" (incomplete)");
+        }
+
+        let mut tooltip_text = format!(
+            "{}\nPath: {}\nSize: {}\n{}",
             rect.name,
             rect.path.display(),
-            size_formatted
+            size_formatted,
+            other_size
         );
+        if rect.kind == NodeKind::Directory {
+            tooltip_text.push_str(&format!(
+                "\nContains: {} files, {} folders, {} other",
+                rect.file_count, rect.dir_count, rect.other_count
+            ));
+        }
+        if rect.kind != NodeKind::Directory {
+            tooltip_text.push_str(&format!("\nType: {}", rect.kind));
+        }
+        if let (Some(newest), Some(oldest)) = (rect.times.newest_mtime, rect.times.oldest_mtime) {
+            if rect.kind == NodeKind::Directory {
+                tooltip_text.push_str(&format!(
+                    "\nModified: {} (newest), {} (oldest)",
+                    format_date(newest),
+                    format_date(oldest)
+                ));
+            } else {
+                tooltip_text.push_str(&format!("\nModified: {}", format_date(newest)));
+            }
+        }
+        if let Some(accessed) = rect.times.newest_atime {
+            tooltip_text.push_str(&format!("\nLast accessed: {}", format_date(accessed)));
+        }
+        if let Some(uid) = rect.owner {
+            tooltip_text.push_str(&format!("\nOwner: {}", owner_names.user(uid)));
+        }
+        if rect.small_items > 0 {
+            tooltip_text.push_str(&format!(
+                "\nMerged: {} items too small to show",
+                rect.small_items
+            ));
+        }
+        if rect.skipped {
+            tooltip_text.push_str("\nMount point (other file system, not scanned)");
+        } else if rect.mount_point {
+            tooltip_text.push_str("\nMount point");
+        }
+        if rect.hard_link_size > 0 {
+            tooltip_text.push_str(&format!(
+                "\nHard linked: {}",
+                format_size(rect.hard_link_size)
+            ));
+        }
 
         // get mouse position
         let mouse_x = app::event_x();
@@ -322,13 +478,49 @@ impl TreemapWidget {
             }
 
             Event::Push => {
-                let data_ref = data.borrow();
-                if let Some(i) = data_ref.hovered_rect {
-                    if let Some(rect) = data_ref.rects.get(i) {
-                        open::that(rect.path.clone()).unwrap_or_else(|_| {
-                            eprintln!("Failed to open path: {}", rect.path.display());
-                        });
+                // copy what we need, the popup menu runs its own event loop
+                let (path, kind, context_menu) = {
+                    let data_ref = data.borrow();
+                    match data_ref.hovered_rect.and_then(|i| data_ref.rects.get(i)) {
+                        Some(rect) => (rect.path.clone(), rect.kind, data_ref.context_menu.clone()),
+                        None => return true,
                     }
+                };
+
+                if app::event_mouse_button() == app::MouseButton::Right {
+                    let choice = context_menu
+                        .popup(app::event_x(), app::event_y())
+                        .and_then(|item| item.label());
+                    match choice.as_deref() {
+                        Some(MENU_OPEN) => Self::open_path(&path),
+                        Some(MENU_REVEAL) => reveal_in_file_manager(&path),
+                        Some(MENU_TYPES) => {
+                            let data_ref = data.borrow();
+                            if let Some(node) = data_ref
+                                .root_node
+                                .as_ref()
+                                .and_then(|root| root.find(&path))
+                            {
+                                show_type_breakdown(&node.name, &node.types, data_ref.size_mode);
+                            }
+                        }
+                        Some(MENU_OWNERS) => {
+                            let data_ref = data.borrow();
+                            if let Some(node) = data_ref
+                                .root_node
+                                .as_ref()
+                                .and_then(|root| root.find(&path))
+                            {
+                                show_owner_breakdown(&node.name, &node.owners, data_ref.size_mode);
+                            }
+                        }
+                        _ => {}
+                    }
+                } else if kind == NodeKind::Directory {
+                    Self::open_path(&path);
+                } else {
+                    // don't launch files by accident
+                    reveal_in_file_manager(&path);
                 }
 
                 true
@@ -354,6 +546,12 @@ impl TreemapWidget {
         }
     }
 
+    fn open_path(path: &std::path::Path) {
+        open::that(path).unwrap_or_else(|_| {
+            eprintln!("Failed to open path: {}", path.display());
+        });
+    }
+
     #[inline]
     pub fn handle_resize(&mut self) {
         self.recalculate_layout();
@@ -363,24 +561,56 @@ impl TreemapWidget {
     fn recalculate_layout(&self) {
         let mut data = self.data.borrow_mut();
         if let Some(ref root) = data.root_node {
-            let rects = self.calculate_hierarchical_treemap(root);
+            let rects = self.calculate_hierarchical_treemap(root, data.size_mode, data.color_mode);
             data.rects = rects;
             data.hovered_rect = None;
         }
     }
 
+    pub fn set_size_mode(&mut self, mode: SizeMode) {
+        self.data.borrow_mut().size_mode = mode;
+        self.handle_resize();
+    }
+
+    // highlight the rectangles containing `paths` (files that may be too small to show)
+    pub fn select_paths(&mut self, paths: &[PathBuf]) {
+        self.data.borrow_mut().selected_paths = paths.to_vec();
+        self.widget.redraw();
+    }
+
+    // run `f` on the currently shown tree
+    pub fn with_root<R>(&self, f: impl FnOnce(&FolderNode, SizeMode) -> R) -> Option<R> {
+        let data = self.data.borrow();
+        data.root_node.as_ref().map(|root| f(root, data.size_mode))
+    }
+
+    pub fn set_color_mode(&mut self, mode: ColorMode) {
+        self.data.borrow_mut().color_mode = mode;
+        self.handle_resize();
+    }
+
     pub fn set_data(&mut self, root: &FolderNode) {
-        let rects = self.calculate_hierarchical_treemap(root);
+        let (size_mode, color_mode) = {
+            let data = self.data.borrow();
+            (data.size_mode, data.color_mode)
+        };
+        let rects = self.calculate_hierarchical_treemap(root, size_mode, color_mode);
 
         let mut data = self.data.borrow_mut();
         data.rects = rects;
         data.hovered_rect = None;
+        data.selected_paths.clear();
         data.root_node = Some(root.clone());
 
         self.widget.redraw();
     }
 
-    fn calculate_hierarchical_treemap(&self, root: &FolderNode) -> Vec<TreemapRect> {
+    fn calculate_hierarchical_treemap(
+        &self,
+        root: &FolderNode,
+        mode: SizeMode,
+        color_mode: ColorMode,
+    ) -> Vec<TreemapRect> {
         let mut rects = Vec::new();
 
         let area = TreemapArea {
@@ -388,10 +618,10 @@ impl TreemapWidget {
             y: self.widget.y(),
             width: self.widget.width(),
             height: self.widget.height(),
-            size: root.size,
+            size: root.size_in(mode),
         };
 
-        self.layout_folder(root, area, 0, &mut rects);
+        self.layout_folder(root, area, 0, mode, color_mode, &mut rects);
         rects
     }
 
@@ -400,6 +630,8 @@ impl TreemapWidget {
         folder: &FolderNode,
         area: TreemapArea,
         depth: u32,
+        mode: SizeMode,
+        color_mode: ColorMode,
         rects: &mut Vec<TreemapRect>,
     ) {
         // skip very small areas
@@ -415,16 +647,29 @@ impl TreemapWidget {
             height: area.height,
             name: folder.name.clone(),
             path: folder.path.clone(),
-            size: folder.size,
+            kind: folder.kind,
+            size: folder.size_in(mode),
+            apparent_size: folder.size,
+            allocated_size: folder.allocated_size,
+            hard_link_size: folder.hard_link_size,
+            file_count: folder.file_count,
+            dir_count: folder.dir_count,
+            other_count: folder.other_count,
+            times: folder.times,
+            owner: folder.dominant_user(mode),
+            incomplete: folder.incomplete,
+            mount_point: folder.mount_point,
+            skipped: folder.skipped,
+            small_items: folder.small_items,
             depth,
-            color: Self::get_color_for_depth(depth),
+            color: Self::get_color_for_node(folder, depth, mode, color_mode),
         });
 
         // filter out zero-sized children and sort by size
         let mut valid_children: Vec<_> = folder
             .children
             .iter()
-            .filter(|child| child.size > 0)
+            .filter(|child| child.size_in(mode) > 0)
             .collect();
 
         if valid_children.is_empty() {
@@ -432,12 +677,12 @@ impl TreemapWidget {
         }
 
         // sort children by size
-        valid_children.sort_by(|a, b| b.size.cmp(&a.size));
+        valid_children.sort_by_key(|child| std::cmp::Reverse(child.size_in(mode)));
 
-        let children_total_size: u64 = valid_children.iter().map(|child| child.size).sum();
+        let children_total_size: u64 = valid_children.iter().map(|child| child.size_in(mode)).sum();
 
         // make children only take up space proportional to their size relative to the parent
-        let children_area_proportion = children_total_size as f64 / folder.size as f64;
+        let children_area_proportion = children_total_size as f64 / folder.size_in(mode) as f64;
 
         // add padding to prevent overlap
         let padding = 4;
@@ -470,27 +715,32 @@ impl TreemapWidget {
             size: children_total_size,
         };
 
-        let child_areas = self.squarify_layout(&valid_children, child_area);
+        let child_areas = self.squarify_layout(&valid_children, child_area, mode);
 
         // recursively layout children
         for (child, child_area) in valid_children.iter().zip(child_areas.iter()) {
-            self.layout_folder(child, *child_area, depth + 1, rects);
+            self.layout_folder(child, *child_area, depth + 1, mode, color_mode, rects);
         }
     }
 
-    fn squarify_layout(&self, children: &[&FolderNode], area: TreemapArea) -> Vec<TreemapArea> {
+    fn squarify_layout(
+        &self,
+        children: &[&FolderNode],
+        area: TreemapArea,
+        mode: SizeMode,
+    ) -> Vec<TreemapArea> {
         if children.is_empty() {
             return Vec::new();
         }
 
         // calculate total size of all children
-        let total_size: u64 = children.iter().map(|child| child.size).sum();
+        let total_size: u64 = children.iter().map(|child| child.size_in(mode)).sum();
         if total_size == 0 {
             return Vec::new();
         }
 
         // get all of the children's sizes
-        let folder_sizes: Vec<u64> = children.iter().map(|child| child.size).collect();
+        let folder_sizes: Vec<u64> = children.iter().map(|child| child.size_in(mode)).collect();
 
         let mut areas = Vec::new();
         let mut remaining_area = area;
@@ -689,10 +939,66 @@ impl TreemapWidget {
         let mut data = self.data.borrow_mut();
         data.rects.clear();
         data.hovered_rect = None;
+        data.selected_paths.clear();
         data.root_node = None;
         self.widget.redraw();
     }
 
+    #[inline(always)]
+    fn get_color_for_node(
+        node: &FolderNode,
+        depth: u32,
+        mode: SizeMode,
+        color_mode: ColorMode,
+    ) -> Color {
+        match node.kind {
+            _ if color_mode == ColorMode::Age => Self::get_color_for_age(node.times.newest_mtime),
+            _ if color_mode == ColorMode::FileType => {
+                let category = node.dominant_category(mode);
+                category.map_or(Color::from_hex(CATPPUCCIN_OVERLAY0), |category| {
+                    Color::from_hex(category.color())
+                })
+            }
+            _ if color_mode == ColorMode::Owner => node
+                .dominant_user(mode)
+                .map_or(Color::from_hex(CATPPUCCIN_OVERLAY0), |uid| {
+                    Color::from_hex(owner_color(uid))
+                }),
+            NodeKind::Directory if node.is_small_items() => Color::from_hex(CATPPUCCIN_OVERLAY0),
+            NodeKind::Directory => Self::get_color_for_depth(depth),
+            NodeKind::File => Color::from_hex(CATPPUCCIN_TEAL),
+            NodeKind::Symlink => Color::from_hex(CATPPUCCIN_SKY),
+            NodeKind::Other => Color::from_hex(CATPPUCCIN_FLAMINGO),
+        }
+    }
+
+    // fresh data is green, data nobody touched in years is red
+    fn get_color_for_age(newest_mtime: Option<SystemTime>) -> Color {
+        let Some(mtime) = newest_mtime else {
+            return Color::from_hex(CATPPUCCIN_OVERLAY0);
+        };
+        let age = SystemTime::now()
+            .duration_since(mtime)
+            .unwrap_or(Duration::ZERO);
+
+        let color = if age < DAY {
+            CATPPUCCIN_GREEN
+        } else if age < DAY * 7 {
+            CATPPUCCIN_TEAL
+        } else if age < DAY * 30 {
+            CATPPUCCIN_BLUE
+        } else if age < DAY * 182 {
+            CATPPUCCIN_YELLOW
+        } else if age < DAY * 365 {
+            CATPPUCCIN_PEACH
+        } else if age < DAY * 365 * 3 {
+            CATPPUCCIN_MAROON
+        } else {
+            CATPPUCCIN_RED
+        };
+        Color::from_hex(color)
+    }
+
     #[inline(always)]
     fn get_color_for_depth(depth: u32) -> Color {
         match depth % 6 {